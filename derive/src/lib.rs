@@ -0,0 +1,213 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned as _;
+use syn::{Data, DeriveInput, Fields, Member, Type, parse_macro_input};
+
+#[proc_macro_derive(Event, attributes(event))]
+pub fn derive_event(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_event(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_event(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (id_type, version_type, id_body, version_body) = match &input.data {
+        Data::Struct(data) => {
+            let accessors = Accessors::from_fields(&data.fields, input.ident.span())?;
+            let (id, version) = (&accessors.id, &accessors.version);
+            let (id_body, version_body) = match &accessors.kind {
+                AccessorsKind::Fields { .. } => (
+                    quote! { ::core::clone::Clone::clone(&self.#id) },
+                    quote! { ::core::clone::Clone::clone(&self.#version) },
+                ),
+                AccessorsKind::Delegate { inner_type } => (
+                    quote! { <#inner_type as ::rust_ddd_traits_lab::v2::Event>::id(&self.#id) },
+                    quote! {
+                        <#inner_type as ::rust_ddd_traits_lab::v2::Event>::version(&self.#version)
+                    },
+                ),
+            };
+            (
+                accessors.id_type(),
+                accessors.version_type(),
+                id_body,
+                version_body,
+            )
+        }
+        Data::Enum(data) => {
+            let mut variants = vec![];
+            for variant in &data.variants {
+                variants.push((&variant.ident, Accessors::from_variant(variant)?));
+            }
+            let (_, first) = variants.first().ok_or_else(|| {
+                syn::Error::new(input.ident.span(), "expected at least one variant")
+            })?;
+            let (id_type, version_type) = (first.id_type(), first.version_type());
+            let id_arms = variants.iter().map(|(variant, accessors)| {
+                let pattern = accessors.pattern(variant);
+                let id = accessors.id_value();
+                quote! { #name::#pattern => #id }
+            });
+            let version_arms = variants.iter().map(|(variant, accessors)| {
+                let pattern = accessors.pattern(variant);
+                let version = accessors.version_value();
+                quote! { #name::#pattern => #version }
+            });
+            (
+                id_type,
+                version_type,
+                quote! { match self { #(#id_arms,)* } },
+                quote! { match self { #(#version_arms,)* } },
+            )
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new(
+                data.union_token.span(),
+                "Event cannot be derived for unions",
+            ));
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::rust_ddd_traits_lab::v2::Event for #name #ty_generics #where_clause {
+            type Id = #id_type;
+            type Version = #version_type;
+
+            fn id(&self) -> Self::Id {
+                #id_body
+            }
+
+            fn version(&self) -> Self::Version {
+                #version_body
+            }
+        }
+    })
+}
+
+// how to get the id and the version of a struct or an enum variant
+struct Accessors {
+    kind: AccessorsKind,
+    id: Member,
+    version: Member,
+}
+
+enum AccessorsKind {
+    // `#[event(id)]` and `#[event(version)]` fields
+    Fields {
+        id_type: Box<Type>,
+        version_type: Box<Type>,
+    },
+    // a single field which implements `Event`
+    Delegate {
+        inner_type: Box<Type>,
+    },
+}
+
+impl Accessors {
+    fn from_fields(fields: &Fields, span: proc_macro2::Span) -> syn::Result<Self> {
+        let mut id = None;
+        let mut version = None;
+        for (index, field) in fields.iter().enumerate() {
+            let member = match &field.ident {
+                None => Member::from(index),
+                Some(ident) => Member::from(ident.clone()),
+            };
+            for attr in field.attrs.iter().filter(|it| it.path().is_ident("event")) {
+                attr.parse_nested_meta(|meta| {
+                    let slot = if meta.path.is_ident("id") {
+                        &mut id
+                    } else if meta.path.is_ident("version") {
+                        &mut version
+                    } else {
+                        return Err(meta.error("expected `id` or `version`"));
+                    };
+                    if slot.is_some() {
+                        return Err(meta.error("duplicate attribute"));
+                    }
+                    *slot = Some((member.clone(), field.ty.clone()));
+                    Ok(())
+                })?;
+            }
+        }
+        match (id, version) {
+            (Some((id, id_type)), Some((version, version_type))) => Ok(Self {
+                kind: AccessorsKind::Fields {
+                    id_type: Box::new(id_type),
+                    version_type: Box::new(version_type),
+                },
+                id,
+                version,
+            }),
+            (None, None) if fields.len() == 1 => {
+                let field = fields.iter().next().expect("one field");
+                let member = match &field.ident {
+                    None => Member::from(0),
+                    Some(ident) => Member::from(ident.clone()),
+                };
+                Ok(Self {
+                    kind: AccessorsKind::Delegate {
+                        inner_type: Box::new(field.ty.clone()),
+                    },
+                    id: member.clone(),
+                    version: member,
+                })
+            }
+            _ => Err(syn::Error::new(
+                span,
+                "expected `#[event(id)]` and `#[event(version)]` fields",
+            )),
+        }
+    }
+
+    fn from_variant(variant: &syn::Variant) -> syn::Result<Self> {
+        Self::from_fields(&variant.fields, variant.ident.span())
+    }
+
+    fn id_type(&self) -> TokenStream {
+        match &self.kind {
+            AccessorsKind::Fields { id_type, .. } => quote! { #id_type },
+            AccessorsKind::Delegate { inner_type } => {
+                quote! { <#inner_type as ::rust_ddd_traits_lab::v2::Event>::Id }
+            }
+        }
+    }
+
+    fn version_type(&self) -> TokenStream {
+        match &self.kind {
+            AccessorsKind::Fields { version_type, .. } => quote! { #version_type },
+            AccessorsKind::Delegate { inner_type } => {
+                quote! { <#inner_type as ::rust_ddd_traits_lab::v2::Event>::Version }
+            }
+        }
+    }
+
+    fn pattern(&self, variant: &syn::Ident) -> TokenStream {
+        let (id, version) = (&self.id, &self.version);
+        match &self.kind {
+            AccessorsKind::Fields { .. } => {
+                quote! { #variant { #id: __id, #version: __version, .. } }
+            }
+            AccessorsKind::Delegate { .. } => quote! { #variant { #id: __inner, .. } },
+        }
+    }
+
+    fn id_value(&self) -> TokenStream {
+        match &self.kind {
+            AccessorsKind::Fields { .. } => quote! { ::core::clone::Clone::clone(__id) },
+            AccessorsKind::Delegate { .. } => {
+                quote! { ::rust_ddd_traits_lab::v2::Event::id(__inner) }
+            }
+        }
+    }
+
+    fn version_value(&self) -> TokenStream {
+        match &self.kind {
+            AccessorsKind::Fields { .. } => quote! { ::core::clone::Clone::clone(__version) },
+            AccessorsKind::Delegate { .. } => {
+                quote! { ::rust_ddd_traits_lab::v2::Event::version(__inner) }
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use rust_ddd_traits_lab::v2::Event;
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+struct AggregateId(String);
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct AggregateVersion(u16);
+
+#[derive(Event)]
+struct AggregateCreated {
+    #[event(id)]
+    id: AggregateId,
+    #[event(version)]
+    version: AggregateVersion,
+    #[allow(dead_code)]
+    name: String,
+}
+
+#[derive(Event)]
+struct AggregateUpdated(#[event(version)] AggregateVersion, #[event(id)] AggregateId);
+
+#[derive(Event)]
+struct Wrapped(AggregateCreated);
+
+#[derive(Event)]
+enum AggregateEvent {
+    Created(AggregateCreated),
+    Updated(AggregateUpdated),
+    Deleted {
+        #[event(id)]
+        id: AggregateId,
+        #[event(version)]
+        version: AggregateVersion,
+    },
+}
+
+#[test]
+fn test_derive_event_for_struct() {
+    let event = AggregateCreated {
+        id: AggregateId("1".to_owned()),
+        version: AggregateVersion(1),
+        name: "name".to_owned(),
+    };
+    assert_eq!(event.id(), AggregateId("1".to_owned()));
+    assert_eq!(event.version(), AggregateVersion(1));
+
+    let event = AggregateUpdated(AggregateVersion(2), AggregateId("1".to_owned()));
+    assert_eq!(event.id(), AggregateId("1".to_owned()));
+    assert_eq!(event.version(), AggregateVersion(2));
+
+    let event = Wrapped(AggregateCreated {
+        id: AggregateId("2".to_owned()),
+        version: AggregateVersion(3),
+        name: "name".to_owned(),
+    });
+    assert_eq!(event.id(), AggregateId("2".to_owned()));
+    assert_eq!(event.version(), AggregateVersion(3));
+}
+
+#[test]
+fn test_derive_event_for_enum() {
+    let events = [
+        AggregateEvent::Created(AggregateCreated {
+            id: AggregateId("1".to_owned()),
+            version: AggregateVersion(1),
+            name: "name".to_owned(),
+        }),
+        AggregateEvent::Updated(AggregateUpdated(
+            AggregateVersion(2),
+            AggregateId("1".to_owned()),
+        )),
+        AggregateEvent::Deleted {
+            id: AggregateId("1".to_owned()),
+            version: AggregateVersion(3),
+        },
+    ];
+    assert_eq!(
+        events
+            .iter()
+            .map(|it| (it.id(), it.version()))
+            .collect::<Vec<_>>(),
+        vec![
+            (AggregateId("1".to_owned()), AggregateVersion(1)),
+            (AggregateId("1".to_owned()), AggregateVersion(2)),
+            (AggregateId("1".to_owned()), AggregateVersion(3)),
+        ]
+    );
+}
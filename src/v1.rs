@@ -1,3 +1,11 @@
+mod in_memory_repository;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use self::in_memory_repository::InMemoryRepository;
+// shared with v2 so that both models report conflicts the same way
+pub use crate::v2::RepositoryError;
+
 pub trait Aggregate: Sized {
     type Id: Eq;
     type Version: Eq + Ord;
@@ -23,28 +31,79 @@ pub trait Repository {
     ) -> Result<(), Self::Error>;
 }
 
+// A deleted aggregate is no longer found and cannot be stored again. `delete` keeps its
+// last state, `purge` also erases it.
+#[async_trait::async_trait]
+pub trait DeletableRepository: Repository {
+    async fn delete(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error>;
+
+    // a deleted aggregate can still be purged
+    async fn purge(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error>;
+}
+
 #[cfg(test)]
-mod tests {
+pub(super) mod tests {
     use super::*;
 
-    #[derive(Debug, Eq, PartialEq)]
-    struct AggregateId(String);
+    #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+    pub(super) struct AggregateId(pub(super) String);
 
-    #[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
-    struct AggregateVersion(u16);
+    #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+    pub(super) struct AggregateVersion(pub(super) u16);
 
-    struct AggregateImpl {
+    #[derive(Clone, Debug)]
+    pub(super) struct AggregateImpl {
         id: AggregateId,
         version: AggregateVersion,
     }
 
     impl AggregateImpl {
-        fn create() -> Self {
+        pub(super) fn create() -> Self {
             Self {
                 id: AggregateId("1".to_owned()),
                 version: AggregateVersion(1),
             }
         }
+
+        pub(super) fn update(&self) -> Self {
+            Self {
+                id: self.id.clone(),
+                version: AggregateVersion(self.version.0 + 1),
+            }
+        }
+    }
+
+    pub(super) struct Fixture<F>(pub(super) F);
+
+    impl<F, R> testing::RepositoryFixture for Fixture<F>
+    where
+        F: Fn() -> R,
+        R: Repository<Aggregate = AggregateImpl>,
+    {
+        type Repository = R;
+
+        fn repository(&self) -> Self::Repository {
+            (self.0)()
+        }
+
+        fn create(&self, n: usize) -> AggregateImpl {
+            AggregateImpl {
+                id: AggregateId(n.to_string()),
+                version: AggregateVersion(1),
+            }
+        }
+
+        fn update(&self, aggregate: &AggregateImpl) -> AggregateImpl {
+            aggregate.update()
+        }
     }
 
     impl Aggregate for AggregateImpl {
@@ -60,29 +119,6 @@ mod tests {
         }
     }
 
-    struct RepositoryImpl;
-
-    #[async_trait::async_trait]
-    impl Repository for RepositoryImpl {
-        type Aggregate = AggregateImpl;
-        type Error = std::io::Error;
-
-        async fn find(
-            &self,
-            _id: &<Self::Aggregate as Aggregate>::Id,
-        ) -> Result<Option<Self::Aggregate>, Self::Error> {
-            unimplemented!()
-        }
-
-        async fn store(
-            &self,
-            _expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
-            _aggregate: &Self::Aggregate,
-        ) -> Result<(), Self::Error> {
-            unimplemented!()
-        }
-    }
-
     #[tokio::test]
     async fn test_aggregate() {
         let aggregate = AggregateImpl::create();
@@ -92,8 +128,19 @@ mod tests {
 
     #[tokio::test]
     async fn test_repository() {
-        let _repository = RepositoryImpl;
+        async fn store_and_find<R>(repository: &R, aggregate: &AggregateImpl) -> AggregateImpl
+        where
+            R: Repository<Aggregate = AggregateImpl>,
+            R::Error: std::fmt::Debug,
+        {
+            repository.store(None, aggregate).await.unwrap();
+            repository.find(aggregate.id()).await.unwrap().unwrap()
+        }
 
-        // ...
+        let repository = InMemoryRepository::<AggregateImpl>::new();
+        let aggregate = AggregateImpl::create();
+        let found = store_and_find(&repository, &aggregate).await;
+        assert_eq!(found.id(), aggregate.id());
+        assert_eq!(found.version(), aggregate.version());
     }
 }
@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use super::{Aggregate, DeletableRepository, Repository, RepositoryError};
+
+pub struct InMemoryRepository<A: Aggregate> {
+    state: Arc<Mutex<State<A>>>,
+}
+
+struct State<A: Aggregate> {
+    aggregates: HashMap<A::Id, A>,
+    // aggregates deleted or purged, which can no longer be stored
+    deleted: HashSet<A::Id>,
+}
+
+impl<A> State<A>
+where
+    A: Aggregate,
+    A::Id: Clone + Hash,
+    A::Version: Clone,
+{
+    fn check_version(
+        &self,
+        id: &A::Id,
+        expected_version: Option<&A::Version>,
+    ) -> Result<(), RepositoryError<A::Id, A::Version, std::convert::Infallible>> {
+        match (expected_version, self.aggregates.get(id)) {
+            (None, None) => {
+                // create
+            }
+            (None, Some(_)) => {
+                return Err(RepositoryError::AlreadyExists { id: id.clone() });
+            }
+            (Some(expected_version), Some(stored)) => {
+                // update
+                if stored.version() != expected_version {
+                    return Err(RepositoryError::VersionConflict {
+                        expected: expected_version.clone(),
+                        actual: stored.version().clone(),
+                    });
+                }
+            }
+            (Some(_), None) => {
+                return Err(RepositoryError::NotFound { id: id.clone() });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<A: Aggregate> InMemoryRepository<A> {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                aggregates: HashMap::new(),
+                deleted: HashSet::new(),
+            })),
+        }
+    }
+}
+
+impl<A: Aggregate> Clone for InMemoryRepository<A> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<A: Aggregate> Default for InMemoryRepository<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> Repository for InMemoryRepository<A>
+where
+    A: Aggregate + Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    type Aggregate = A;
+    type Error = RepositoryError<A::Id, A::Version, std::convert::Infallible>;
+
+    async fn find(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<Option<Self::Aggregate>, Self::Error> {
+        let state = self.state.lock().unwrap();
+        if state.deleted.contains(id) {
+            return Ok(None);
+        }
+        Ok(state.aggregates.get(id).cloned())
+    }
+
+    async fn store(
+        &self,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        aggregate: &Self::Aggregate,
+    ) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let id = aggregate.id();
+        if state.deleted.contains(id) {
+            return Err(RepositoryError::Deleted { id: id.clone() });
+        }
+        state.check_version(id, expected_version)?;
+        state.aggregates.insert(id.clone(), aggregate.clone());
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> DeletableRepository for InMemoryRepository<A>
+where
+    A: Aggregate + Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    async fn delete(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.deleted.contains(id) {
+            return Err(RepositoryError::Deleted { id: id.clone() });
+        }
+        state.check_version(id, Some(expected_version))?;
+        state.deleted.insert(id.clone());
+        Ok(())
+    }
+
+    async fn purge(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        if !state.aggregates.contains_key(id) && state.deleted.contains(id) {
+            // already purged
+            return Err(RepositoryError::Deleted { id: id.clone() });
+        }
+        state.check_version(id, Some(expected_version))?;
+        state.aggregates.remove(id);
+        state.deleted.insert(id.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v1::testing::repository_conformance;
+    use crate::v1::tests::{AggregateId, AggregateImpl, AggregateVersion, Fixture};
+
+    #[tokio::test]
+    async fn test_find_and_store() {
+        let repository = InMemoryRepository::<AggregateImpl>::new();
+        let aggregate = AggregateImpl::create();
+
+        assert!(repository.find(aggregate.id()).await.unwrap().is_none());
+        repository.store(None, &aggregate).await.unwrap();
+        assert_eq!(
+            repository
+                .find(aggregate.id())
+                .await
+                .unwrap()
+                .unwrap()
+                .version(),
+            &AggregateVersion(1)
+        );
+
+        let updated = aggregate.update();
+        repository
+            .store(Some(aggregate.version()), &updated)
+            .await
+            .unwrap();
+        assert_eq!(
+            repository
+                .find(&AggregateId("1".to_owned()))
+                .await
+                .unwrap()
+                .unwrap()
+                .version(),
+            &AggregateVersion(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_rejects_conflicts() {
+        let repository = InMemoryRepository::<AggregateImpl>::new();
+        let aggregate = AggregateImpl::create();
+        let updated = aggregate.update();
+
+        assert!(matches!(
+            repository.store(Some(aggregate.version()), &updated).await,
+            Err(RepositoryError::NotFound { .. })
+        ));
+        repository.store(None, &aggregate).await.unwrap();
+        assert!(matches!(
+            repository.store(None, &aggregate).await,
+            Err(RepositoryError::AlreadyExists { .. })
+        ));
+        assert!(matches!(
+            repository
+                .store(Some(updated.version()), &updated.update())
+                .await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_purge() {
+        let repository = InMemoryRepository::<AggregateImpl>::new();
+        let aggregate = AggregateImpl::create();
+        let id = aggregate.id();
+        repository.store(None, &aggregate).await.unwrap();
+
+        assert!(matches!(
+            repository.delete(id, &AggregateVersion(2)).await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+        repository.delete(id, aggregate.version()).await.unwrap();
+        assert!(repository.find(id).await.unwrap().is_none());
+        assert!(matches!(
+            repository
+                .store(Some(aggregate.version()), &aggregate.update())
+                .await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+        assert!(matches!(
+            repository.delete(id, aggregate.version()).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+
+        repository.purge(id, aggregate.version()).await.unwrap();
+        assert!(!repository.state.lock().unwrap().aggregates.contains_key(id));
+        assert!(matches!(
+            repository.purge(id, aggregate.version()).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+        assert!(matches!(
+            repository.store(None, &aggregate).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        repository_conformance(&Fixture(InMemoryRepository::<AggregateImpl>::new)).await;
+    }
+}
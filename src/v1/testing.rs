@@ -0,0 +1,191 @@
+use std::fmt::Debug;
+use std::pin::Pin;
+
+use super::{Aggregate, Repository};
+use crate::v2::testing::race;
+
+pub trait RepositoryFixture {
+    type Repository: Repository;
+
+    fn repository(&self) -> Self::Repository;
+
+    fn create(&self, n: usize) -> <Self::Repository as Repository>::Aggregate;
+
+    fn update(
+        &self,
+        aggregate: &<Self::Repository as Repository>::Aggregate,
+    ) -> <Self::Repository as Repository>::Aggregate;
+}
+
+pub async fn repository_conformance<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Id: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    find_missing(fixture).await;
+    create_and_update(fixture).await;
+    create_twice(fixture).await;
+    stale_expected_version(fixture).await;
+    expected_version_for_missing(fixture).await;
+    multiple_aggregates(fixture).await;
+    concurrent_writers(fixture).await;
+}
+
+async fn find_missing<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+{
+    let repository = fixture.repository();
+    let aggregate = fixture.create(1);
+    assert!(repository.find(aggregate.id()).await.unwrap().is_none());
+}
+
+async fn create_and_update<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Id: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+
+    let aggregate = fixture.create(1);
+    repository.store(None, &aggregate).await.unwrap();
+    let found = repository.find(aggregate.id()).await.unwrap().unwrap();
+    assert_eq!(found.id(), aggregate.id());
+    assert_eq!(found.version(), aggregate.version());
+
+    let updated = fixture.update(&found);
+    repository
+        .store(Some(found.version()), &updated)
+        .await
+        .unwrap();
+    let found = repository.find(aggregate.id()).await.unwrap().unwrap();
+    assert_eq!(found.id(), updated.id());
+    assert_eq!(found.version(), updated.version());
+}
+
+async fn create_twice<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+
+    let aggregate = fixture.create(1);
+    repository.store(None, &aggregate).await.unwrap();
+    let updated = fixture.update(&aggregate);
+    assert!(repository.store(None, &updated).await.is_err());
+
+    let found = repository.find(aggregate.id()).await.unwrap().unwrap();
+    assert_eq!(found.version(), aggregate.version());
+}
+
+async fn stale_expected_version<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+
+    let stale = fixture.create(1);
+    repository.store(None, &stale).await.unwrap();
+    let updated = fixture.update(&stale);
+    repository
+        .store(Some(stale.version()), &updated)
+        .await
+        .unwrap();
+
+    let stale_updated = fixture.update(&stale);
+    assert!(
+        repository
+            .store(Some(stale.version()), &stale_updated)
+            .await
+            .is_err()
+    );
+
+    let found = repository.find(stale.id()).await.unwrap().unwrap();
+    assert_eq!(found.version(), updated.version());
+}
+
+async fn expected_version_for_missing<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+{
+    let repository = fixture.repository();
+
+    let aggregate = fixture.create(1);
+    let updated = fixture.update(&aggregate);
+    assert!(
+        repository
+            .store(Some(aggregate.version()), &updated)
+            .await
+            .is_err()
+    );
+    assert!(repository.find(aggregate.id()).await.unwrap().is_none());
+}
+
+async fn multiple_aggregates<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Id: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+
+    let aggregate1 = fixture.create(1);
+    let aggregate2 = fixture.create(2);
+    repository.store(None, &aggregate1).await.unwrap();
+    repository.store(None, &aggregate2).await.unwrap();
+    let updated1 = fixture.update(&aggregate1);
+    repository
+        .store(Some(aggregate1.version()), &updated1)
+        .await
+        .unwrap();
+
+    let found1 = repository.find(aggregate1.id()).await.unwrap().unwrap();
+    assert_eq!(found1.id(), updated1.id());
+    assert_eq!(found1.version(), updated1.version());
+    let found2 = repository.find(aggregate2.id()).await.unwrap().unwrap();
+    assert_eq!(found2.id(), aggregate2.id());
+    assert_eq!(found2.version(), aggregate2.version());
+}
+
+async fn concurrent_writers<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+
+    // a store that is not atomic fails only some of the races
+    for n in 1..=8 {
+        let aggregate = fixture.create(n);
+        repository.store(None, &aggregate).await.unwrap();
+        let updated = (0..4)
+            .map(|_| fixture.update(&aggregate))
+            .collect::<Vec<_>>();
+
+        let results = race(
+            updated
+                .iter()
+                .map(|updated| {
+                    let store = repository.store(Some(aggregate.version()), updated);
+                    Box::pin(async move { store.await.is_ok() }) as Pin<Box<_>>
+                })
+                .collect(),
+        );
+        assert_eq!(results.iter().filter(|ok| **ok).count(), 1);
+
+        let found = repository.find(aggregate.id()).await.unwrap().unwrap();
+        assert_eq!(found.version(), updated[0].version());
+    }
+}
@@ -1,3 +1,70 @@
+mod channel_publisher;
+mod command;
+mod direction;
+mod event_codec;
+mod event_envelope;
+mod expiry_policy;
+mod file_event_store;
+mod find_at_error;
+mod in_memory_checkpoint_store;
+mod in_memory_event_store;
+mod in_memory_publisher;
+mod in_memory_snapshot_store;
+#[cfg(feature = "json")]
+mod json_codec;
+mod outbox;
+#[cfg(feature = "postcard")]
+mod postcard_codec;
+mod projection;
+mod recorded_event;
+mod replay_error;
+mod repository_error;
+mod retry;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite_event_store;
+mod stream_append;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod upcaster;
+mod v1_adapter;
+
+pub use self::channel_publisher::ChannelPublisher;
+pub use self::command::{CommandError, execute};
+pub use self::direction::Direction;
+pub use self::event_codec::{EncodedEvent, EventCodec, EventSchema};
+pub use self::event_envelope::EventEnvelope;
+pub use self::expiry_policy::ExpiryPolicy;
+pub use self::file_event_store::{FileEventStore, FileEventStoreError};
+pub use self::find_at_error::FindAtError;
+pub use self::in_memory_checkpoint_store::InMemoryCheckpointStore;
+pub use self::in_memory_event_store::{CancelHandle, InMemoryEventStore, InMemorySubscription};
+pub use self::in_memory_publisher::InMemoryPublisher;
+pub use self::in_memory_snapshot_store::InMemorySnapshotStore;
+#[cfg(feature = "json")]
+pub use self::json_codec::JsonCodec;
+pub use self::outbox::{Outbox, OutboxDisabled, OutboxEntry, OutboxError, OutboxRelay, Publisher};
+#[cfg(feature = "postcard")]
+pub use self::postcard_codec::PostcardCodec;
+pub use self::projection::{CheckpointStore, Projection, ProjectionError, ProjectionRunner};
+pub use self::recorded_event::{Position, RecordedDeletion, RecordedEvent};
+pub use self::replay_error::ReplayError;
+pub use self::repository_error::RepositoryError;
+pub use self::retry::{RetryPolicy, execute_with_retry};
+pub use self::snapshot::{
+    Snapshot, SnapshotPolicy, SnapshotRepository, SnapshotRepositoryError, SnapshotStore,
+};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite_event_store::{SqliteEventStore, SqliteEventStoreError};
+pub use self::stream_append::StreamAppend;
+pub use self::upcaster::{UpcastError, Upcaster, UpcasterChain, UpcastingCodec};
+pub use self::v1_adapter::{PendingAggregate, V1Adapter, V1AdapterError, import_v1};
+#[cfg(feature = "derive")]
+pub use rust_ddd_traits_lab_derive::Event;
+
+use std::ops::{Bound, RangeBounds as _};
+use std::time::SystemTime;
+
 pub trait Event {
     type Id: Eq;
     type Version: Eq + Ord;
@@ -7,14 +74,26 @@ pub trait Event {
 }
 
 pub trait Aggregate: Sized {
-    type Error: std::error::Error;
+    type Error: std::error::Error + From<ReplayError>;
     type Event: Event<Id = Self::Id, Version = Self::Version>;
     type Id: Eq;
     type Version: Eq + Ord;
 
+    fn init(event: Self::Event) -> Result<Self, Self::Error>;
+
+    fn apply(self, event: Self::Event) -> Result<Self, Self::Error>;
+
     fn replay<I>(events: I) -> Result<Self, Self::Error>
     where
-        I: IntoIterator<Item = Self::Event>;
+        I: IntoIterator<Item = Self::Event>,
+    {
+        let mut iter = events.into_iter();
+        let aggregate = match iter.next() {
+            None => return Err(ReplayError::NoEvents.into()),
+            Some(event) => Self::init(event)?,
+        };
+        iter.try_fold(aggregate, Self::apply)
+    }
 
     fn id(&self) -> Self::Id;
     fn version(&self) -> Self::Version;
@@ -38,12 +117,215 @@ pub trait Repository {
     ) -> Result<(), Self::Error>;
 }
 
+#[async_trait::async_trait]
+pub trait EventReader: Repository {
+    async fn read_events(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        after_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+    ) -> Result<Vec<<Self::Aggregate as Aggregate>::Event>, Self::Error>;
+
+    // whether the stream was deleted through `DeletableRepository`, in which case its events
+    // can still be read but the aggregate is no longer found
+    async fn is_deleted(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<bool, Self::Error>;
+
+    // returns at most `limit` events with versions within the bounds, in `direction`; the
+    // next page starts after the version of the last event returned
+    async fn read_stream(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        from_version: Bound<&<Self::Aggregate as Aggregate>::Version>,
+        to_version: Bound<&<Self::Aggregate as Aggregate>::Version>,
+        direction: Direction,
+        limit: usize,
+    ) -> Result<Vec<<Self::Aggregate as Aggregate>::Event>, Self::Error>
+    where
+        <Self::Aggregate as Aggregate>::Id: Sync,
+        <Self::Aggregate as Aggregate>::Version: Sync,
+    {
+        let after_version = match from_version {
+            Bound::Excluded(version) => Some(version),
+            Bound::Included(_) | Bound::Unbounded => None,
+        };
+        let events = self
+            .read_events(id, after_version)
+            .await?
+            .into_iter()
+            .filter(|event| (from_version, to_version).contains(&event.version()));
+        Ok(match direction {
+            Direction::Forward => events.take(limit).collect(),
+            Direction::Backward => events.rev().take(limit).collect(),
+        })
+    }
+
+    // replays the events up to and including `version`; a deleted aggregate is not found at
+    // any version, like it is not found by `find`
+    #[allow(clippy::type_complexity)]
+    async fn find_at(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<
+        Option<Self::Aggregate>,
+        FindAtError<Self::Error, <Self::Aggregate as Aggregate>::Error>,
+    >
+    where
+        <Self::Aggregate as Aggregate>::Id: Sync,
+        <Self::Aggregate as Aggregate>::Version: Sync,
+    {
+        if self.is_deleted(id).await.map_err(FindAtError::Repository)? {
+            return Ok(None);
+        }
+        let events = self
+            .read_stream(
+                id,
+                Bound::Unbounded,
+                Bound::Included(version),
+                Direction::Forward,
+                usize::MAX,
+            )
+            .await
+            .map_err(FindAtError::Repository)?;
+        if events.is_empty() {
+            return Ok(None);
+        }
+        Self::Aggregate::replay(events)
+            .map(Some)
+            .map_err(FindAtError::Aggregate)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait EnvelopeRepository: EventReader {
+    async fn store_envelopes(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[EventEnvelope<<Self::Aggregate as Aggregate>::Event>],
+    ) -> Result<(), Self::Error>;
+
+    async fn read_envelopes(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        after_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+    ) -> Result<Vec<EventEnvelope<<Self::Aggregate as Aggregate>::Event>>, Self::Error>;
+
+    // replays the events recorded at or before `recorded_at`, relying on the stores to record
+    // times that never go back along a stream; a deleted aggregate is not found at any time
+    #[allow(clippy::type_complexity)]
+    async fn find_at_time(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        recorded_at: SystemTime,
+    ) -> Result<
+        Option<Self::Aggregate>,
+        FindAtError<Self::Error, <Self::Aggregate as Aggregate>::Error>,
+    >
+    where
+        <Self::Aggregate as Aggregate>::Id: Sync,
+    {
+        if self.is_deleted(id).await.map_err(FindAtError::Repository)? {
+            return Ok(None);
+        }
+        let events = self
+            .read_envelopes(id, None)
+            .await
+            .map_err(FindAtError::Repository)?
+            .into_iter()
+            .take_while(|envelope| envelope.recorded_at <= recorded_at)
+            .map(|envelope| envelope.event)
+            .collect::<Vec<_>>();
+        if events.is_empty() {
+            return Ok(None);
+        }
+        Self::Aggregate::replay(events)
+            .map(Some)
+            .map_err(FindAtError::Aggregate)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait MultiStreamRepository: Repository {
+    // stores the events of all the streams, or none of them if any version check fails
+    #[allow(clippy::type_complexity)]
+    async fn store_many(
+        &self,
+        appends: &[StreamAppend<
+            <Self::Aggregate as Aggregate>::Id,
+            <Self::Aggregate as Aggregate>::Version,
+            <Self::Aggregate as Aggregate>::Event,
+        >],
+    ) -> Result<(), Self::Error>;
+}
+
+// Command ids are scoped to their stream. Only successful stores are remembered, so a batch
+// rejected with an error can be retried under the same command id.
+#[async_trait::async_trait]
+pub trait IdempotentRepository: Repository {
+    // returns the version of the stream right after the batch first stored under `command_id`
+    async fn store_idempotent(
+        &self,
+        command_id: &str,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[<Self::Aggregate as Aggregate>::Event],
+    ) -> Result<Option<<Self::Aggregate as Aggregate>::Version>, Self::Error>;
+}
+
+// A deleted aggregate is no longer found and its stream accepts no more events. `delete`
+// records a tombstone and keeps the events, `purge` also erases them. The tombstone is kept
+// beside the stream rather than appended to it, since a stream only holds events of the
+// aggregate; `EventLog::read_deletions` exposes it to readers of the log.
+#[async_trait::async_trait]
+pub trait DeletableRepository: Repository {
+    async fn delete(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error>;
+
+    // a deleted stream can still be purged, unless it already was
+    async fn purge(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error>;
+}
+
+#[async_trait::async_trait]
+pub trait EventLog: Repository {
+    async fn read_all(
+        &self,
+        from_position: Position,
+        limit: usize,
+    ) -> Result<Vec<RecordedEvent<<Self::Aggregate as Aggregate>::Event>>, Self::Error>;
+
+    // deletions take positions in the log like events do, and are skipped by `read_all`
+    async fn read_deletions(
+        &self,
+        from_position: Position,
+        limit: usize,
+    ) -> Result<Vec<RecordedDeletion<<Self::Aggregate as Aggregate>::Id>>, Self::Error>;
+}
+
+pub trait Subscribe: EventLog {
+    type Subscription: futures_core::Stream<
+            Item = Result<RecordedEvent<<Self::Aggregate as Aggregate>::Event>, Self::Error>,
+        > + Send
+        + Unpin;
+
+    fn subscribe(&self, from_position: Position) -> Self::Subscription;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[derive(Clone)]
-    enum AggregateEvent {
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub(super) enum AggregateEvent {
         Created(AggregateCreated),
         Updated(AggregateUpdated),
     }
@@ -71,38 +353,131 @@ mod tests {
         }
     }
 
-    #[derive(Clone)]
-    struct AggregateCreated {
-        id: String,
-        version: u16,
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub(super) struct AggregateCreated {
+        pub(super) id: String,
+        pub(super) version: u16,
     }
 
-    #[derive(Clone)]
-    struct AggregateUpdated {
-        id: String,
-        version: u16,
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub(super) struct AggregateUpdated {
+        pub(super) id: String,
+        pub(super) version: u16,
     }
 
-    #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
-    struct AggregateId(String);
+    #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+    pub(super) struct AggregateId(pub(super) String);
+
+    impl std::fmt::Display for AggregateId {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.0.fmt(f)
+        }
+    }
 
     #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
-    struct AggregateVersion(u16);
+    pub(super) struct AggregateVersion(pub(super) u16);
+
+    pub(super) struct AggregateEventCodec;
+
+    impl EventCodec for AggregateEventCodec {
+        type Event = AggregateEvent;
+        type Error = std::io::Error;
+
+        fn encode(&self, event: &Self::Event) -> Result<EncodedEvent, Self::Error> {
+            let (event_type, id, version) = match event {
+                AggregateEvent::Created(AggregateCreated { id, version }) => {
+                    ("AggregateCreated", id, version)
+                }
+                AggregateEvent::Updated(AggregateUpdated { id, version }) => {
+                    ("AggregateUpdated", id, version)
+                }
+            };
+            let mut payload = version.to_le_bytes().to_vec();
+            payload.extend_from_slice(id.as_bytes());
+            Ok(EncodedEvent {
+                event_type: event_type.to_owned(),
+                schema_version: 1,
+                payload,
+            })
+        }
+
+        fn decode(&self, encoded: &EncodedEvent) -> Result<Self::Event, Self::Error> {
+            let invalid = || std::io::Error::other("Invalid bytes");
+            let (version, id) = match encoded.payload.as_slice() {
+                [v0, v1, id @ ..] => (u16::from_le_bytes([*v0, *v1]), id),
+                _ => return Err(invalid()),
+            };
+            let id = String::from_utf8(id.to_vec()).map_err(|_| invalid())?;
+            match encoded.event_type.as_str() {
+                "AggregateCreated" => Ok(AggregateEvent::Created(AggregateCreated { id, version })),
+                "AggregateUpdated" => Ok(AggregateEvent::Updated(AggregateUpdated { id, version })),
+                _ => Err(invalid()),
+            }
+        }
+    }
+
+    pub(super) fn created(id: &str, version: u16) -> AggregateEvent {
+        AggregateEvent::Created(AggregateCreated {
+            id: id.to_owned(),
+            version,
+        })
+    }
+
+    pub(super) async fn create<R>(repository: &R, id: &str)
+    where
+        R: Repository<Aggregate = AggregateImpl>,
+        R::Error: std::fmt::Debug,
+    {
+        repository
+            .store(&AggregateId(id.to_owned()), None, &[created(id, 1)])
+            .await
+            .unwrap();
+    }
 
-    struct AggregateImpl {
-        id: AggregateId,
-        version: AggregateVersion,
+    pub(super) struct Fixture<F>(pub(super) F);
+
+    impl<F, R> testing::RepositoryFixture for Fixture<F>
+    where
+        F: Fn() -> R,
+        R: Repository<Aggregate = AggregateImpl>,
+    {
+        type Repository = R;
+
+        fn repository(&self) -> Self::Repository {
+            (self.0)()
+        }
+
+        fn id(&self, n: usize) -> AggregateId {
+            AggregateId(n.to_string())
+        }
+
+        fn create(&self, id: &AggregateId) -> Vec<AggregateEvent> {
+            vec![AggregateEvent::Created(AggregateCreated {
+                id: id.0.clone(),
+                version: 1,
+            })]
+        }
+
+        fn update(&self, aggregate: &AggregateImpl) -> Vec<AggregateEvent> {
+            let (_, events) = aggregate.update().unwrap();
+            events
+        }
+    }
+
+    pub(super) struct AggregateImpl {
+        pub(super) id: AggregateId,
+        pub(super) version: AggregateVersion,
     }
 
     impl AggregateImpl {
-        fn create() -> Self {
+        pub(super) fn create() -> Self {
             Self {
                 id: AggregateId("1".to_owned()),
                 version: AggregateVersion(1),
             }
         }
 
-        fn update(&self) -> Result<(Self, Vec<AggregateEvent>), std::io::Error> {
+        pub(super) fn update(&self) -> Result<(Self, Vec<AggregateEvent>), std::io::Error> {
             let new_version = self.version.0 + 1;
             let event = AggregateEvent::Updated(AggregateUpdated {
                 id: self.id.0.clone(),
@@ -124,41 +499,24 @@ mod tests {
         type Id = AggregateId;
         type Version = AggregateVersion;
 
-        fn replay<I>(events: I) -> Result<Self, Self::Error>
-        where
-            I: IntoIterator<Item = Self::Event>,
-        {
-            let mut iter = events.into_iter();
-            let mut aggregate = match iter.next() {
-                None => Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "No events provided",
-                )),
-                Some(event) => match event {
-                    AggregateEvent::Created(AggregateCreated { id, version }) => Ok(Self {
-                        id: AggregateId(id),
-                        version: AggregateVersion(version),
-                    }),
-                    _ => Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "Invalid event",
-                    )),
-                },
-            }?;
-            for event in iter {
-                match event {
-                    AggregateEvent::Created(_) => {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            "Invalid event",
-                        ));
-                    }
-                    AggregateEvent::Updated(_) => {
-                        aggregate.version = event.version();
-                    }
-                }
+        fn init(event: Self::Event) -> Result<Self, Self::Error> {
+            match event {
+                AggregateEvent::Created(AggregateCreated { id, version }) => Ok(Self {
+                    id: AggregateId(id),
+                    version: AggregateVersion(version),
+                }),
+                AggregateEvent::Updated(_) => Err(std::io::Error::other("Invalid event")),
+            }
+        }
+
+        fn apply(self, event: Self::Event) -> Result<Self, Self::Error> {
+            match event {
+                AggregateEvent::Created(_) => Err(std::io::Error::other("Invalid event")),
+                AggregateEvent::Updated(_) => Ok(Self {
+                    version: event.version(),
+                    ..self
+                }),
             }
-            Ok(aggregate)
         }
 
         fn id(&self) -> Self::Id {
@@ -172,13 +530,14 @@ mod tests {
 
     struct RepositoryImpl {
         aggregates: std::sync::Arc<std::sync::Mutex<Vec<(AggregateId, AggregateVersion)>>>,
+        #[allow(clippy::type_complexity)]
         events: std::sync::Arc<std::sync::Mutex<Vec<(AggregateId, Vec<AggregateEvent>)>>>,
     }
 
     #[async_trait::async_trait]
     impl Repository for RepositoryImpl {
         type Aggregate = AggregateImpl;
-        type Error = std::io::Error;
+        type Error = RepositoryError<AggregateId, AggregateVersion, std::io::Error>;
 
         async fn find(
             &self,
@@ -193,7 +552,9 @@ mod tests {
                         None => return Ok(None),
                         Some((_, events)) => events,
                     };
-                    Self::Aggregate::replay(events.clone()).map(Some)
+                    Self::Aggregate::replay(events.clone())
+                        .map(Some)
+                        .map_err(RepositoryError::Backend)
                 }
             }
         }
@@ -214,10 +575,7 @@ mod tests {
                 None => {
                     // create
                     if aggregates.iter().any(|it| &it.0 == id) {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::Other,
-                            "Aggregate already exists",
-                        ));
+                        return Err(RepositoryError::AlreadyExists { id: id.clone() });
                     }
                     aggregates.push((last_event.id(), last_event.version()));
                 }
@@ -228,11 +586,14 @@ mod tests {
                         Some(it) if it.1 == *expected_version => {
                             it.1 = last_event.version();
                         }
-                        None | Some(_) => {
-                            return Err(std::io::Error::new(
-                                std::io::ErrorKind::Other,
-                                "Version mismatch",
-                            ));
+                        Some(it) => {
+                            return Err(RepositoryError::VersionConflict {
+                                expected: expected_version.clone(),
+                                actual: it.1.clone(),
+                            });
+                        }
+                        None => {
+                            return Err(RepositoryError::NotFound { id: id.clone() });
                         }
                     }
                 }
@@ -260,6 +621,46 @@ mod tests {
         assert_eq!(aggregate.version(), AggregateVersion(1));
     }
 
+    #[tokio::test]
+    async fn test_replay() {
+        let aggregate = AggregateImpl::replay(vec![
+            AggregateEvent::Created(AggregateCreated {
+                id: "1".to_owned(),
+                version: 1,
+            }),
+            AggregateEvent::Updated(AggregateUpdated {
+                id: "1".to_owned(),
+                version: 2,
+            }),
+        ])
+        .unwrap();
+        assert_eq!(aggregate.id(), AggregateId("1".to_owned()));
+        assert_eq!(aggregate.version(), AggregateVersion(2));
+
+        let error = AggregateImpl::replay(vec![]).err().unwrap();
+        assert_eq!(error.to_string(), "No events provided");
+
+        assert!(
+            AggregateImpl::replay(vec![AggregateEvent::Updated(AggregateUpdated {
+                id: "1".to_owned(),
+                version: 2,
+            })])
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply() {
+        let aggregate = AggregateImpl::create();
+        let (updated_aggregate, events) = aggregate.update().unwrap();
+
+        let aggregate = events
+            .into_iter()
+            .try_fold(aggregate, AggregateImpl::apply)
+            .unwrap();
+        assert_eq!(aggregate.version(), updated_aggregate.version());
+    }
+
     #[tokio::test]
     async fn test_repository() {
         let repository = RepositoryImpl {
@@ -0,0 +1,44 @@
+use std::sync::mpsc::{SendError, Sender};
+
+use super::{EventEnvelope, Publisher};
+
+pub struct ChannelPublisher<E> {
+    sender: Sender<EventEnvelope<E>>,
+}
+
+impl<E> ChannelPublisher<E> {
+    pub fn new(sender: Sender<EventEnvelope<E>>) -> Self {
+        Self { sender }
+    }
+}
+
+#[async_trait::async_trait]
+impl<E> Publisher for ChannelPublisher<E>
+where
+    E: Clone + Send + Sync,
+{
+    type Event = E;
+    type Error = SendError<EventEnvelope<E>>;
+
+    async fn publish(&self, envelope: &EventEnvelope<Self::Event>) -> Result<(), Self::Error> {
+        self.sender.send(envelope.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let publisher = ChannelPublisher::new(sender);
+        let envelope = EventEnvelope::new(1);
+
+        publisher.publish(&envelope).await.unwrap();
+        assert_eq!(receiver.try_recv().unwrap(), envelope);
+
+        drop(receiver);
+        assert!(publisher.publish(&envelope).await.is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use super::{Aggregate, Repository};
+
+#[derive(Debug)]
+pub enum CommandError<R, A, D> {
+    Repository(R),
+    Aggregate(A),
+    Rejected(D),
+    NothingToCreate,
+}
+
+impl<R, A, D> std::fmt::Display for CommandError<R, A, D>
+where
+    R: std::fmt::Display,
+    A: std::fmt::Display,
+    D: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Repository(e) => std::fmt::Display::fmt(e, f),
+            Self::Aggregate(e) => std::fmt::Display::fmt(e, f),
+            Self::Rejected(e) => std::fmt::Display::fmt(e, f),
+            Self::NothingToCreate => write!(f, "No events to create the aggregate with"),
+        }
+    }
+}
+
+impl<R, A, D> std::error::Error for CommandError<R, A, D>
+where
+    R: std::error::Error + 'static,
+    A: std::error::Error + 'static,
+    D: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Repository(e) => Some(e),
+            Self::Aggregate(e) => Some(e),
+            Self::Rejected(e) => Some(e),
+            Self::NothingToCreate => None,
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub async fn execute<R, F, D>(
+    repository: &R,
+    id: &<R::Aggregate as Aggregate>::Id,
+    decide: F,
+) -> Result<R::Aggregate, CommandError<R::Error, <R::Aggregate as Aggregate>::Error, D>>
+where
+    R: Repository,
+    F: FnOnce(Option<&R::Aggregate>) -> Result<Vec<<R::Aggregate as Aggregate>::Event>, D>,
+{
+    let aggregate = repository
+        .find(id)
+        .await
+        .map_err(CommandError::Repository)?;
+    let events = decide(aggregate.as_ref()).map_err(CommandError::Rejected)?;
+    if aggregate.is_none() && events.is_empty() {
+        return Err(CommandError::NothingToCreate);
+    }
+    let expected_version = aggregate.as_ref().map(Aggregate::version);
+    repository
+        .store(id, expected_version.as_ref(), &events)
+        .await
+        .map_err(CommandError::Repository)?;
+    match aggregate {
+        // create
+        None => R::Aggregate::replay(events),
+        // update
+        Some(aggregate) => events.into_iter().try_fold(aggregate, Aggregate::apply),
+    }
+    .map_err(CommandError::Aggregate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::tests::{
+        AggregateCreated, AggregateEvent, AggregateId, AggregateImpl, AggregateVersion,
+    };
+    use crate::v2::{InMemoryEventStore, RepositoryError};
+
+    fn create(id: &AggregateId) -> Result<Vec<AggregateEvent>, std::io::Error> {
+        Ok(vec![AggregateEvent::Created(AggregateCreated {
+            id: id.0.clone(),
+            version: 1,
+        })])
+    }
+
+    fn update(aggregate: &AggregateImpl) -> Result<Vec<AggregateEvent>, std::io::Error> {
+        aggregate.update().map(|(_, events)| events)
+    }
+
+    #[tokio::test]
+    async fn test_execute() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        let created = execute(&repository, &id, |aggregate| match aggregate {
+            None => create(&id),
+            Some(_) => Err(std::io::Error::other("Aggregate already exists")),
+        })
+        .await
+        .unwrap();
+        assert_eq!(created.version(), AggregateVersion(1));
+
+        let updated = execute(&repository, &id, |aggregate| update(aggregate.unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(updated.version(), AggregateVersion(2));
+        assert_eq!(
+            repository.find(&id).await.unwrap().unwrap().version(),
+            AggregateVersion(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_rejected() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        let result = execute(&repository, &id, |aggregate| match aggregate {
+            None => Err(std::io::Error::other("Aggregate not found")),
+            Some(aggregate) => update(aggregate),
+        })
+        .await;
+        assert!(matches!(result, Err(CommandError::Rejected(_))));
+        assert!(repository.find(&id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_execute_nothing_to_create() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        let result = execute(&repository, &id, |_| Ok::<_, std::io::Error>(vec![])).await;
+        assert!(matches!(result, Err(CommandError::NothingToCreate)));
+        assert!(repository.find(&id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_execute_conflict() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        execute(&repository, &id, |_| create(&id)).await.unwrap();
+
+        let result = execute(&repository, &id, |aggregate| {
+            // a concurrent writer stores first
+            let events = update(aggregate.unwrap())?;
+            futures::executor::block_on(repository.store(&id, Some(&AggregateVersion(1)), &events))
+                .unwrap();
+            Ok::<_, std::io::Error>(events)
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(CommandError::Repository(
+                RepositoryError::VersionConflict { .. }
+            ))
+        ));
+    }
+}
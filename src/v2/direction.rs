@@ -0,0 +1,6 @@
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Direction {
+    #[default]
+    Forward,
+    Backward,
+}
@@ -0,0 +1,54 @@
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncodedEvent {
+    pub event_type: String,
+    pub schema_version: u32,
+    pub payload: Vec<u8>,
+}
+
+pub trait EventSchema {
+    fn event_type(&self) -> &'static str;
+
+    fn schema_version(&self) -> u32 {
+        1
+    }
+}
+
+pub trait EventCodec {
+    type Event;
+    type Error: std::error::Error;
+
+    fn encode(&self, event: &Self::Event) -> Result<EncodedEvent, Self::Error>;
+    fn decode(&self, encoded: &EncodedEvent) -> Result<Self::Event, Self::Error>;
+
+    // stores read through this so that upcasters can split or drop stored events
+    fn decode_all(&self, encoded: &EncodedEvent) -> Result<Vec<Self::Event>, Self::Error> {
+        self.decode(encoded).map(|event| vec![event])
+    }
+}
+
+// a payload is only accepted as the type and schema version it was stored under,
+// so older schema versions have to pass through an upcaster before they decode. Errors expect
+// what the record states and report what the payload decoded to.
+#[cfg(any(feature = "json", feature = "postcard"))]
+pub(crate) fn check_schema<E, Err>(event: E, encoded: &EncodedEvent) -> Result<E, Err>
+where
+    E: EventSchema,
+    Err: serde::de::Error,
+{
+    if event.event_type() != encoded.event_type {
+        return Err(Err::custom(format_args!(
+            "Unexpected event type (expected = {}, actual = {})",
+            encoded.event_type,
+            event.event_type()
+        )));
+    }
+    if event.schema_version() != encoded.schema_version {
+        return Err(Err::custom(format_args!(
+            "Unexpected schema version for {} (expected = {}, actual = {})",
+            encoded.event_type,
+            encoded.schema_version,
+            event.schema_version()
+        )));
+    }
+    Ok(event)
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EventEnvelope<E> {
+    pub event_id: uuid::Uuid,
+    // overwritten by the store when the event is appended
+    pub recorded_at: SystemTime,
+    pub correlation_id: Option<uuid::Uuid>,
+    pub causation_id: Option<uuid::Uuid>,
+    pub actor: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    pub event: E,
+}
+
+impl<E> EventEnvelope<E> {
+    pub fn new(event: E) -> Self {
+        Self {
+            event_id: uuid::Uuid::new_v4(),
+            recorded_at: SystemTime::now(),
+            correlation_id: None,
+            causation_id: None,
+            actor: None,
+            headers: BTreeMap::new(),
+            event,
+        }
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: uuid::Uuid) -> Self {
+        self.correlation_id = Some(correlation_id);
+        self
+    }
+
+    pub fn with_causation_id(mut self, causation_id: uuid::Uuid) -> Self {
+        self.causation_id = Some(causation_id);
+        self
+    }
+
+    pub fn with_actor<S: Into<String>>(mut self, actor: S) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn with_header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    // an envelope for an event caused by this one, in the same correlation
+    pub fn caused<T>(&self, event: T) -> EventEnvelope<T> {
+        let mut envelope = EventEnvelope::new(event);
+        envelope.correlation_id = Some(self.correlation_id.unwrap_or(self.event_id));
+        envelope.causation_id = Some(self.event_id);
+        envelope.actor = self.actor.clone();
+        envelope
+    }
+
+    pub fn as_ref(&self) -> EventEnvelope<&E> {
+        EventEnvelope {
+            event_id: self.event_id,
+            recorded_at: self.recorded_at,
+            correlation_id: self.correlation_id,
+            causation_id: self.causation_id,
+            actor: self.actor.clone(),
+            headers: self.headers.clone(),
+            event: &self.event,
+        }
+    }
+
+    pub fn map<T, F: FnOnce(E) -> T>(self, f: F) -> EventEnvelope<T> {
+        EventEnvelope {
+            event_id: self.event_id,
+            recorded_at: self.recorded_at,
+            correlation_id: self.correlation_id,
+            causation_id: self.causation_id,
+            actor: self.actor,
+            headers: self.headers,
+            event: f(self.event),
+        }
+    }
+}
+
+// the time a store records a batch at, which never goes back along a stream even if the clock does
+pub(super) fn recorded_at(previous: Option<SystemTime>) -> SystemTime {
+    let now = SystemTime::now();
+    previous.map_or(now, |previous| previous.max(now))
+}
+
+// metadata = event id (16) + recorded at (secs u64 LE + nanos u32 LE)
+//   + correlation id (flag + 16) + causation id (flag + 16) + actor (flag + string)
+//   + header count (u32 LE) + headers (string + string)
+// string = length (u32 LE) + utf-8 bytes
+pub(super) fn encode_metadata<E>(envelope: &EventEnvelope<E>) -> Vec<u8> {
+    fn put_string(bytes: &mut Vec<u8>, s: &str) {
+        bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(s.as_bytes());
+    }
+
+    let mut bytes = vec![];
+    bytes.extend_from_slice(envelope.event_id.as_bytes());
+    let since_epoch = envelope
+        .recorded_at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    bytes.extend_from_slice(&since_epoch.as_secs().to_le_bytes());
+    bytes.extend_from_slice(&since_epoch.subsec_nanos().to_le_bytes());
+    for id in [envelope.correlation_id, envelope.causation_id] {
+        match id {
+            None => bytes.push(0),
+            Some(id) => {
+                bytes.push(1);
+                bytes.extend_from_slice(id.as_bytes());
+            }
+        }
+    }
+    match &envelope.actor {
+        None => bytes.push(0),
+        Some(actor) => {
+            bytes.push(1);
+            put_string(&mut bytes, actor);
+        }
+    }
+    bytes.extend_from_slice(&(envelope.headers.len() as u32).to_le_bytes());
+    for (key, value) in &envelope.headers {
+        put_string(&mut bytes, key);
+        put_string(&mut bytes, value);
+    }
+    bytes
+}
+
+// returns `None` if `bytes` is not metadata written by `encode_metadata`
+pub(super) fn decode_metadata<E>(bytes: &[u8], event: E) -> Option<EventEnvelope<E>> {
+    struct Reader<'a>(&'a [u8]);
+
+    impl Reader<'_> {
+        fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+            let (chunk, rest) = self.0.split_first_chunk::<N>()?;
+            self.0 = rest;
+            Some(*chunk)
+        }
+
+        fn flag(&mut self) -> Option<bool> {
+            match self.take::<1>()? {
+                [0] => Some(false),
+                [1] => Some(true),
+                _ => None,
+            }
+        }
+
+        fn uuid(&mut self) -> Option<uuid::Uuid> {
+            self.take::<16>().map(uuid::Uuid::from_bytes)
+        }
+
+        fn string(&mut self) -> Option<String> {
+            let len = u32::from_le_bytes(self.take::<4>()?) as usize;
+            let s = String::from_utf8(self.0.get(..len)?.to_vec()).ok()?;
+            self.0 = &self.0[len..];
+            Some(s)
+        }
+    }
+
+    let mut reader = Reader(bytes);
+    let event_id = reader.uuid()?;
+    let secs = u64::from_le_bytes(reader.take::<8>()?);
+    let nanos = u32::from_le_bytes(reader.take::<4>()?);
+    let recorded_at = SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs, nanos))?;
+    let correlation_id = if reader.flag()? {
+        Some(reader.uuid()?)
+    } else {
+        None
+    };
+    let causation_id = if reader.flag()? {
+        Some(reader.uuid()?)
+    } else {
+        None
+    };
+    let actor = if reader.flag()? {
+        Some(reader.string()?)
+    } else {
+        None
+    };
+    let header_count = u32::from_le_bytes(reader.take::<4>()?);
+    let mut headers = BTreeMap::new();
+    for _ in 0..header_count {
+        headers.insert(reader.string()?, reader.string()?);
+    }
+    if !reader.0.is_empty() {
+        return None;
+    }
+    Some(EventEnvelope {
+        event_id,
+        recorded_at,
+        correlation_id,
+        causation_id,
+        actor,
+        headers,
+        event,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caused() {
+        let command = EventEnvelope::new(()).with_actor("alice");
+        let first = command.caused(1);
+        let second = first.caused(2);
+
+        assert_eq!(first.correlation_id, Some(command.event_id));
+        assert_eq!(first.causation_id, Some(command.event_id));
+        assert_eq!(second.correlation_id, Some(command.event_id));
+        assert_eq!(second.causation_id, Some(first.event_id));
+        assert_eq!(second.actor.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let envelope = EventEnvelope::new(())
+            .with_correlation_id(uuid::Uuid::new_v4())
+            .with_actor("alice")
+            .with_header("trace-id", "abc")
+            .with_header("reason", "");
+
+        let bytes = encode_metadata(&envelope);
+        assert_eq!(decode_metadata(&bytes, ()), Some(envelope));
+        assert_eq!(decode_metadata(&bytes[..bytes.len() - 1], ()), None);
+    }
+}
@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExpiryPolicy {
+    Never,
+    After(Duration),
+}
+
+impl ExpiryPolicy {
+    pub fn is_expired(&self, age: Duration) -> bool {
+        match self {
+            Self::Never => false,
+            Self::After(ttl) => age >= *ttl,
+        }
+    }
+}
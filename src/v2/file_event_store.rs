@@ -0,0 +1,1033 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use super::event_envelope::{decode_metadata, encode_metadata, recorded_at};
+use super::{
+    Aggregate, DeletableRepository, EncodedEvent, EnvelopeRepository, Event, EventCodec,
+    EventEnvelope, EventReader, Repository, RepositoryError,
+};
+
+// record = body length (u32 LE) + crc32 of body length (u32 LE) + crc32 of body (u32 LE)
+//   + body
+// body = metadata length (u32 LE) + metadata + event type length (u16 LE) + event type
+//   + schema version (u32 LE) + payload
+const HEADER_LEN: usize = 12;
+
+// Appends hold an exclusive lock on the log file and reads a shared one, so several processes
+// can use the same directory. A deleted stream has an empty tombstone file next to its log,
+// and purging it also truncates the log.
+pub struct FileEventStore<A: Aggregate, C> {
+    dir: PathBuf,
+    codec: C,
+    // length, and version and recorded time of the last event, of each log as of the last append
+    // or full read from this store, valid as long as the length of the file is unchanged
+    tails: Mutex<HashMap<PathBuf, Tail<A>>>,
+    _aggregate: std::marker::PhantomData<fn() -> A>,
+}
+
+type Tail<A> = (u64, Option<(<A as Aggregate>::Version, SystemTime)>);
+
+// longest id whose hex encoding, with the `.deleted` extension of a tombstone, fits in the
+// 255 bytes most file systems allow for a file name
+const MAX_ID_LEN: usize = 123;
+
+impl<A, C> FileEventStore<A, C>
+where
+    A: Aggregate,
+    A::Id: Display,
+    C: EventCodec<Event = A::Event>,
+{
+    pub fn open<P: AsRef<Path>>(dir: P, codec: C) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            codec,
+            tails: Mutex::new(HashMap::new()),
+            _aggregate: std::marker::PhantomData,
+        })
+    }
+
+    fn path(&self, id: &A::Id) -> Result<PathBuf, FileEventStoreError<C::Error, A::Error>> {
+        let id = id.to_string();
+        if id.len() > MAX_ID_LEN {
+            return Err(FileEventStoreError::IdTooLong { len: id.len() });
+        }
+        // hex-encode the id so that any id maps to a safe file name
+        let name = id.bytes().map(|b| format!("{:02x}", b)).collect::<String>();
+        Ok(self.dir.join(format!("{}.log", name)))
+    }
+
+    fn has_tombstone(&self, path: &Path) -> Result<bool, FileEventStoreError<C::Error, A::Error>> {
+        tombstone_path(path)
+            .try_exists()
+            .map_err(FileEventStoreError::Io)
+    }
+
+    fn sync_dir(&self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        File::open(&self.dir)?.sync_all()?;
+        Ok(())
+    }
+
+    // returns the decoded events and the length of the valid prefix of the file
+    #[allow(clippy::type_complexity)]
+    fn read_log(
+        &self,
+        path: &Path,
+    ) -> Result<Option<(Vec<EventEnvelope<A::Event>>, u64)>, FileEventStoreError<C::Error, A::Error>>
+    {
+        let mut bytes = vec![];
+        match File::open(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(FileEventStoreError::Io(e)),
+            Ok(mut file) => {
+                file.lock_shared().map_err(FileEventStoreError::Io)?;
+                file.read_to_end(&mut bytes)
+                    .map_err(FileEventStoreError::Io)?
+            }
+        };
+        self.decode_log(path, &bytes).map(Some)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn decode_log(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+    ) -> Result<(Vec<EventEnvelope<A::Event>>, u64), FileEventStoreError<C::Error, A::Error>> {
+        let mut events = vec![];
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let corrupted = || FileEventStoreError::Corrupted {
+                path: path.to_path_buf(),
+                offset: offset as u64,
+            };
+            let header = match bytes.get(offset..offset + HEADER_LEN) {
+                // torn header
+                None => break,
+                Some(header) => header,
+            };
+            let (len, checksums) = header.split_at(4);
+            if crc32fast::hash(len) != u32::from_le_bytes(checksums[0..4].try_into().unwrap()) {
+                // without a valid length, the record cannot be told to be the final one
+                return Err(corrupted());
+            }
+            let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+            let checksum = u32::from_le_bytes(checksums[4..8].try_into().unwrap());
+            let end = offset + HEADER_LEN + len;
+            let body = match bytes.get(offset + HEADER_LEN..end) {
+                // torn body
+                None => break,
+                Some(body) => body,
+            };
+            if crc32fast::hash(body) != checksum {
+                if end == bytes.len() {
+                    // torn final record
+                    break;
+                }
+                return Err(corrupted());
+            }
+            let (metadata, encoded) = decode_body(body).ok_or_else(corrupted)?;
+            // events split by an upcaster share the metadata of their record
+            for event in self
+                .codec
+                .decode_all(&encoded)
+                .map_err(FileEventStoreError::Codec)?
+            {
+                events.push(decode_metadata(metadata, event).ok_or_else(corrupted)?);
+            }
+            offset = end;
+        }
+        Ok((events, offset as u64))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn append(
+        &self,
+        id: &A::Id,
+        expected_version: Option<&A::Version>,
+        new_events: &[EventEnvelope<&A::Event>],
+    ) -> Result<(), RepositoryError<A::Id, A::Version, FileEventStoreError<C::Error, A::Error>>>
+    where
+        A::Id: Clone,
+        A::Version: Clone,
+    {
+        if new_events.is_empty() {
+            return Ok(());
+        }
+
+        let encoded = new_events
+            .iter()
+            .map(|envelope| self.codec.encode(envelope.event))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RepositoryError::Backend(FileEventStoreError::Codec(e)))?;
+
+        let io_error = |e| RepositoryError::Backend(FileEventStoreError::Io(e));
+        let path = self.path(id).map_err(RepositoryError::Backend)?;
+        let created = !path.exists();
+        if created && expected_version.is_some() {
+            return Err(RepositoryError::NotFound { id: id.clone() });
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(io_error)?;
+        file.lock().map_err(io_error)?;
+        if self
+            .has_tombstone(&path)
+            .map_err(RepositoryError::Backend)?
+        {
+            return Err(RepositoryError::Deleted { id: id.clone() });
+        }
+        let len = file.metadata().map_err(io_error)?.len();
+        let tail = self
+            .tails
+            .lock()
+            .unwrap()
+            .get(&path)
+            .filter(|(tail_len, _)| *tail_len == len)
+            .map(|(_, last)| last.clone());
+        let (last, valid_len) = match tail {
+            Some(last) => (last, len),
+            None => {
+                let mut bytes = vec![];
+                file.read_to_end(&mut bytes).map_err(io_error)?;
+                let (events, valid_len) = self
+                    .decode_log(&path, &bytes)
+                    .map_err(RepositoryError::Backend)?;
+                let last = events
+                    .last()
+                    .map(|envelope| (envelope.event.version(), envelope.recorded_at));
+                if valid_len == len {
+                    self.tails
+                        .lock()
+                        .unwrap()
+                        .insert(path.clone(), (len, last.clone()));
+                }
+                (last, valid_len)
+            }
+        };
+        let (actual_version, last_recorded_at) = last.unzip();
+        match (expected_version, actual_version) {
+            (None, None) => {
+                // create
+            }
+            (None, Some(_)) => {
+                return Err(RepositoryError::AlreadyExists { id: id.clone() });
+            }
+            (Some(expected_version), Some(actual_version)) => {
+                // update
+                if actual_version != *expected_version {
+                    return Err(RepositoryError::VersionConflict {
+                        expected: expected_version.clone(),
+                        actual: actual_version,
+                    });
+                }
+            }
+            (Some(_), None) => {
+                return Err(RepositoryError::NotFound { id: id.clone() });
+            }
+        }
+
+        let recorded_at = recorded_at(last_recorded_at);
+        let mut buf = vec![];
+        for (envelope, encoded) in new_events.iter().zip(&encoded) {
+            let metadata = encode_metadata(&EventEnvelope {
+                recorded_at,
+                ..envelope.clone()
+            });
+            let body = encode_body(&metadata, encoded).map_err(RepositoryError::Backend)?;
+            let len = encode_len::<u32, _, _>(body.len())
+                .map_err(RepositoryError::Backend)?
+                .to_le_bytes();
+            buf.extend_from_slice(&len);
+            buf.extend_from_slice(&crc32fast::hash(&len).to_le_bytes());
+            buf.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+            buf.extend_from_slice(&body);
+        }
+
+        // drop a torn final record left by a crash before appending
+        file.set_len(valid_len).map_err(io_error)?;
+        file.seek(SeekFrom::Start(valid_len)).map_err(io_error)?;
+        file.write_all(&buf).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        if created {
+            self.sync_dir().map_err(io_error)?;
+        }
+        let last = new_events
+            .last()
+            .map(|envelope| (envelope.event.version(), recorded_at));
+        self.tails
+            .lock()
+            .unwrap()
+            .insert(path, (valid_len + buf.len() as u64, last));
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn remove(
+        &self,
+        id: &A::Id,
+        expected_version: &A::Version,
+        purge: bool,
+    ) -> Result<(), RepositoryError<A::Id, A::Version, FileEventStoreError<C::Error, A::Error>>>
+    where
+        A::Id: Clone,
+        A::Version: Clone,
+    {
+        let io_error = |e| RepositoryError::Backend(FileEventStoreError::Io(e));
+        let path = self.path(id).map_err(RepositoryError::Backend)?;
+        let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(RepositoryError::NotFound { id: id.clone() });
+            }
+            Err(e) => return Err(io_error(e)),
+            Ok(file) => file,
+        };
+        file.lock().map_err(io_error)?;
+        let deleted = self
+            .has_tombstone(&path)
+            .map_err(RepositoryError::Backend)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes).map_err(io_error)?;
+        let (events, _) = self
+            .decode_log(&path, &bytes)
+            .map_err(RepositoryError::Backend)?;
+        let actual_version = events.last().map(|envelope| envelope.event.version());
+        if deleted && (!purge || actual_version.is_none()) {
+            return Err(RepositoryError::Deleted { id: id.clone() });
+        }
+        match actual_version {
+            None => return Err(RepositoryError::NotFound { id: id.clone() }),
+            Some(actual_version) if actual_version != *expected_version => {
+                return Err(RepositoryError::VersionConflict {
+                    expected: expected_version.clone(),
+                    actual: actual_version,
+                });
+            }
+            Some(_) => {}
+        }
+
+        // the tombstone is durable before the events are erased, so a purge interrupted by a
+        // crash leaves a deleted stream that can be purged again
+        if !deleted {
+            File::create(tombstone_path(&path))
+                .and_then(|tombstone| tombstone.sync_all())
+                .map_err(io_error)?;
+            self.sync_dir().map_err(io_error)?;
+        }
+        if purge {
+            file.set_len(0).map_err(io_error)?;
+            file.sync_all().map_err(io_error)?;
+            self.tails.lock().unwrap().insert(path, (0, None));
+        }
+        Ok(())
+    }
+}
+
+fn tombstone_path(path: &Path) -> PathBuf {
+    path.with_extension("deleted")
+}
+
+#[async_trait::async_trait]
+impl<A, C> Repository for FileEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Clone + Debug + Display + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    type Aggregate = A;
+    type Error = RepositoryError<A::Id, A::Version, FileEventStoreError<C::Error, A::Error>>;
+
+    async fn find(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<Option<Self::Aggregate>, Self::Error> {
+        let path = self.path(id).map_err(RepositoryError::Backend)?;
+        if self
+            .has_tombstone(&path)
+            .map_err(RepositoryError::Backend)?
+        {
+            return Ok(None);
+        }
+        let events = {
+            match self.read_log(&path).map_err(RepositoryError::Backend)? {
+                Some((events, _)) if !events.is_empty() => events,
+                _ => return Ok(None),
+            }
+        };
+        A::replay(events.into_iter().map(|envelope| envelope.event))
+            .map(Some)
+            .map_err(|e| RepositoryError::Backend(FileEventStoreError::Aggregate(e)))
+    }
+
+    async fn store(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[<Self::Aggregate as Aggregate>::Event],
+    ) -> Result<(), Self::Error> {
+        let new_events = new_events
+            .iter()
+            .map(EventEnvelope::new)
+            .collect::<Vec<_>>();
+        self.append(id, expected_version, &new_events)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, C> EventReader for FileEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Clone + Debug + Display + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    async fn read_events(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        after_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+    ) -> Result<Vec<<Self::Aggregate as Aggregate>::Event>, Self::Error> {
+        let events = match self
+            .read_log(&self.path(id).map_err(RepositoryError::Backend)?)
+            .map_err(RepositoryError::Backend)?
+        {
+            None => return Ok(vec![]),
+            Some((events, _)) => events,
+        };
+        Ok(events
+            .into_iter()
+            .map(|envelope| envelope.event)
+            .filter(|event| {
+                after_version.is_none_or(|after_version| event.version() > *after_version)
+            })
+            .collect())
+    }
+
+    async fn is_deleted(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<bool, Self::Error> {
+        self.path(id)
+            .and_then(|path| self.has_tombstone(&path))
+            .map_err(RepositoryError::Backend)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, C> DeletableRepository for FileEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Clone + Debug + Display + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    async fn delete(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        self.remove(id, expected_version, false)
+    }
+
+    async fn purge(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        self.remove(id, expected_version, true)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, C> EnvelopeRepository for FileEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Clone + Debug + Display + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    async fn store_envelopes(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[EventEnvelope<<Self::Aggregate as Aggregate>::Event>],
+    ) -> Result<(), Self::Error> {
+        let new_events = new_events
+            .iter()
+            .map(EventEnvelope::as_ref)
+            .collect::<Vec<_>>();
+        self.append(id, expected_version, &new_events)
+    }
+
+    async fn read_envelopes(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        after_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+    ) -> Result<Vec<EventEnvelope<<Self::Aggregate as Aggregate>::Event>>, Self::Error> {
+        let envelopes = match self
+            .read_log(&self.path(id).map_err(RepositoryError::Backend)?)
+            .map_err(RepositoryError::Backend)?
+        {
+            None => return Ok(vec![]),
+            Some((envelopes, _)) => envelopes,
+        };
+        Ok(envelopes
+            .into_iter()
+            .filter(|envelope| {
+                after_version.is_none_or(|after_version| envelope.event.version() > *after_version)
+            })
+            .collect())
+    }
+}
+
+fn encode_len<T, C, A>(len: usize) -> Result<T, FileEventStoreError<C, A>>
+where
+    T: TryFrom<usize>,
+{
+    T::try_from(len).map_err(|_| FileEventStoreError::RecordTooLarge { len })
+}
+
+fn encode_body<C, A>(
+    metadata: &[u8],
+    encoded: &EncodedEvent,
+) -> Result<Vec<u8>, FileEventStoreError<C, A>> {
+    let event_type = encoded.event_type.as_bytes();
+    let mut body =
+        Vec::with_capacity(4 + metadata.len() + 2 + event_type.len() + 4 + encoded.payload.len());
+    body.extend_from_slice(&encode_len::<u32, _, _>(metadata.len())?.to_le_bytes());
+    body.extend_from_slice(metadata);
+    body.extend_from_slice(&encode_len::<u16, _, _>(event_type.len())?.to_le_bytes());
+    body.extend_from_slice(event_type);
+    body.extend_from_slice(&encoded.schema_version.to_le_bytes());
+    body.extend_from_slice(&encoded.payload);
+    Ok(body)
+}
+
+fn decode_body(body: &[u8]) -> Option<(&[u8], EncodedEvent)> {
+    let (len, rest) = body.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    let metadata = rest.get(..len)?;
+    let (len, rest) = rest[len..].split_first_chunk::<2>()?;
+    let len = u16::from_le_bytes(*len) as usize;
+    let event_type = String::from_utf8(rest.get(..len)?.to_vec()).ok()?;
+    let (schema_version, payload) = rest[len..].split_first_chunk::<4>()?;
+    Some((
+        metadata,
+        EncodedEvent {
+            event_type,
+            schema_version: u32::from_le_bytes(*schema_version),
+            payload: payload.to_vec(),
+        },
+    ))
+}
+
+#[derive(Debug)]
+pub enum FileEventStoreError<C, A> {
+    Io(std::io::Error),
+    Codec(C),
+    Aggregate(A),
+    Corrupted { path: PathBuf, offset: u64 },
+    IdTooLong { len: usize },
+    RecordTooLarge { len: usize },
+}
+
+impl<C, A> Display for FileEventStoreError<C, A>
+where
+    C: Display,
+    A: Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => Display::fmt(e, f),
+            Self::Codec(e) => Display::fmt(e, f),
+            Self::Aggregate(e) => Display::fmt(e, f),
+            Self::Corrupted { path, offset } => write!(
+                f,
+                "Corrupted record (path = {}, offset = {})",
+                path.display(),
+                offset
+            ),
+            Self::IdTooLong { len } => write!(f, "Aggregate id too long (len = {})", len),
+            Self::RecordTooLarge { len } => write!(f, "Record too large (len = {})", len),
+        }
+    }
+}
+
+impl<C, A> std::error::Error for FileEventStoreError<C, A>
+where
+    C: std::error::Error + 'static,
+    A: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Codec(e) => Some(e),
+            Self::Aggregate(e) => Some(e),
+            Self::Corrupted { .. } | Self::IdTooLong { .. } | Self::RecordTooLarge { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::v2::testing::repository_conformance;
+    use crate::v2::tests::{
+        AggregateEventCodec, AggregateId, AggregateImpl, AggregateVersion, Fixture, created,
+    };
+
+    fn open(dir: &Path) -> FileEventStore<AggregateImpl, AggregateEventCodec> {
+        FileEventStore::open(dir, AggregateEventCodec).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_find_and_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let id = AggregateId("1".to_owned());
+
+        assert!(repository.find(&id).await.unwrap().is_none());
+
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), AggregateVersion(1));
+
+        let (updated_aggregate, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id, Some(&found_aggregate.version()), &events)
+            .await
+            .unwrap();
+
+        // reopen
+        let repository = open(dir.path());
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), updated_aggregate.version());
+        assert_eq!(
+            repository
+                .read_events(&id, Some(&AggregateVersion(1)))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_rejects_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let id = AggregateId("1".to_owned());
+
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(1)), &[created("1", 1)])
+                .await,
+            Err(RepositoryError::NotFound { .. })
+        ));
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository.store(&id, None, &[created("1", 1)]).await,
+            Err(RepositoryError::AlreadyExists { .. })
+        ));
+
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        let (_, events) = found_aggregate.update().unwrap();
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(2)), &events)
+                .await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recover_torn_final_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let id = AggregateId("1".to_owned());
+
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let path = repository.path(&id).unwrap();
+        let valid_len = std::fs::metadata(&path).unwrap().len();
+
+        // simulate a crash in the middle of writing the next record
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        let (_, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(std::fs::metadata(&path).unwrap().len() - 1)
+            .unwrap();
+
+        let repository = open(dir.path());
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), AggregateVersion(1));
+
+        let (_, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > valid_len);
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), AggregateVersion(2));
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let id = AggregateId("1".to_owned());
+
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        let (_, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+
+        let path = repository.path(&id).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[HEADER_LEN] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        assert!(matches!(
+            repository.find(&id).await,
+            Err(RepositoryError::Backend(FileEventStoreError::Corrupted {
+                offset: 0,
+                ..
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let id = AggregateId("1".to_owned());
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        for version in 1..3 {
+            let (_, events) = repository
+                .find(&id)
+                .await
+                .unwrap()
+                .unwrap()
+                .update()
+                .unwrap();
+            repository
+                .store(&id, Some(&AggregateVersion(version)), &events)
+                .await
+                .unwrap();
+        }
+
+        let path = repository.path(&id).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        let len = bytes.len() as u64;
+        bytes[0] ^= 0x01;
+        std::fs::write(&path, bytes).unwrap();
+
+        // reopen, as the tail cached by the first store does not notice a change in place
+        let repository = open(dir.path());
+
+        assert!(matches!(
+            repository.find(&id).await,
+            Err(RepositoryError::Backend(FileEventStoreError::Corrupted {
+                offset: 0,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            repository.store(&id, None, &[created("1", 1)]).await,
+            Err(RepositoryError::Backend(FileEventStoreError::Corrupted {
+                offset: 0,
+                ..
+            }))
+        ));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[tokio::test]
+    async fn test_id_too_long() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let longest = "a".repeat(MAX_ID_LEN);
+        repository
+            .store(&AggregateId(longest.clone()), None, &[created(&longest, 1)])
+            .await
+            .unwrap();
+        repository
+            .delete(&AggregateId(longest.clone()), &AggregateVersion(1))
+            .await
+            .unwrap();
+
+        let id = AggregateId("a".repeat(MAX_ID_LEN + 1));
+        assert!(matches!(
+            repository.store(&id, None, &[created(&id.0, 1)]).await,
+            Err(RepositoryError::Backend(FileEventStoreError::IdTooLong {
+                len
+            })) if len == MAX_ID_LEN + 1
+        ));
+        assert!(matches!(
+            repository.find(&id).await,
+            Err(RepositoryError::Backend(
+                FileEventStoreError::IdTooLong { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_record_too_large() {
+        let encoded = EncodedEvent {
+            event_type: "a".repeat(usize::from(u16::MAX) + 1),
+            schema_version: 1,
+            payload: vec![],
+        };
+        assert!(matches!(
+            encode_body::<std::io::Error, std::io::Error>(&[], &encoded),
+            Err(FileEventStoreError::RecordTooLarge { len }) if len == usize::from(u16::MAX) + 1
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_stores() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = AggregateId("1".to_owned());
+        let barrier = std::sync::Barrier::new(8);
+
+        // each store stands for a separate process sharing the directory
+        let results = std::thread::scope(|scope| {
+            let handles = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let repository = open(dir.path());
+                        barrier.wait();
+                        futures::executor::block_on(repository.store(&id, None, &[created("1", 1)]))
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(
+            results.iter().all(|result| matches!(
+                result,
+                Ok(()) | Err(RepositoryError::AlreadyExists { .. })
+            ))
+        );
+        let repository = open(dir.path());
+        assert_eq!(
+            futures::executor::block_on(repository.read_events(&id, None))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stores_sharing_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = open(dir.path());
+        let second = open(dir.path());
+        let id = AggregateId("1".to_owned());
+
+        first.store(&id, None, &[created("1", 1)]).await.unwrap();
+        let (_, events) = second.find(&id).await.unwrap().unwrap().update().unwrap();
+        second
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+
+        // the tail cached by `first` is stale
+        assert!(matches!(
+            first.store(&id, Some(&AggregateVersion(1)), &events).await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+        let (_, events) = first.find(&id).await.unwrap().unwrap().update().unwrap();
+        first
+            .store(&id, Some(&AggregateVersion(2)), &events)
+            .await
+            .unwrap();
+        assert_eq!(
+            second.find(&id).await.unwrap().unwrap().version(),
+            AggregateVersion(3)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upcast_on_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let id = AggregateId("1".to_owned());
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let (_, events) = repository
+            .find(&id)
+            .await
+            .unwrap()
+            .unwrap()
+            .update()
+            .unwrap();
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+
+        // AggregateUpdated became obsolete
+        let upcaster = |event: EncodedEvent| -> Result<_, std::io::Error> {
+            match event.event_type.as_str() {
+                "AggregateUpdated" => Ok(vec![]),
+                _ => Ok(vec![event]),
+            }
+        };
+        let repository = FileEventStore::<AggregateImpl, _>::open(
+            dir.path(),
+            crate::v2::UpcastingCodec::new(AggregateEventCodec, upcaster),
+        )
+        .unwrap();
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), AggregateVersion(1));
+        assert_eq!(repository.read_events(&id, None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_envelopes() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let id = AggregateId("1".to_owned());
+        let mut envelope = EventEnvelope::new(created("1", 1))
+            .with_correlation_id(uuid::Uuid::new_v4())
+            .with_actor("alice")
+            .with_header("trace-id", "abc");
+        // the store records its own time
+        envelope.recorded_at = std::time::SystemTime::UNIX_EPOCH;
+        let before = std::time::SystemTime::now();
+
+        repository
+            .store_envelopes(&id, None, std::slice::from_ref(&envelope))
+            .await
+            .unwrap();
+
+        // reopen
+        let repository = open(dir.path());
+        let envelopes = repository.read_envelopes(&id, None).await.unwrap();
+        assert!(envelopes[0].recorded_at >= before);
+        assert_eq!(
+            envelopes,
+            vec![EventEnvelope {
+                recorded_at: envelopes[0].recorded_at,
+                ..envelope
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_purge() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = open(dir.path());
+        let id = AggregateId("1".to_owned());
+
+        assert!(matches!(
+            repository.delete(&id, &AggregateVersion(1)).await,
+            Err(RepositoryError::NotFound { .. })
+        ));
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository.delete(&id, &AggregateVersion(2)).await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+        repository.delete(&id, &AggregateVersion(1)).await.unwrap();
+        assert!(repository.is_deleted(&id).await.unwrap());
+        assert!(repository.find(&id).await.unwrap().is_none());
+        assert_eq!(repository.read_events(&id, None).await.unwrap().len(), 1);
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(1)), &[created("1", 2)])
+                .await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+        assert!(matches!(
+            repository.delete(&id, &AggregateVersion(1)).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+
+        repository.purge(&id, &AggregateVersion(1)).await.unwrap();
+        assert!(repository.read_events(&id, None).await.unwrap().is_empty());
+        assert!(matches!(
+            repository.purge(&id, &AggregateVersion(1)).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+
+        // the tombstone is persisted
+        let repository = open(dir.path());
+        assert!(matches!(
+            repository.store(&id, None, &[created("1", 1)]).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        let dir = tempfile::tempdir().unwrap();
+        let n = AtomicUsize::new(0);
+        repository_conformance(&Fixture(|| -> FileEventStore<AggregateImpl, _> {
+            FileEventStore::open(
+                dir.path()
+                    .join(n.fetch_add(1, Ordering::SeqCst).to_string()),
+                AggregateEventCodec,
+            )
+            .unwrap()
+        }))
+        .await;
+    }
+}
@@ -0,0 +1,31 @@
+#[derive(Debug)]
+pub enum FindAtError<R, A> {
+    Repository(R),
+    Aggregate(A),
+}
+
+impl<R, A> std::fmt::Display for FindAtError<R, A>
+where
+    R: std::fmt::Display,
+    A: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Repository(e) => std::fmt::Display::fmt(e, f),
+            Self::Aggregate(e) => std::fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<R, A> std::error::Error for FindAtError<R, A>
+where
+    R: std::error::Error + 'static,
+    A: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Repository(e) => Some(e),
+            Self::Aggregate(e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::{CheckpointStore, Position};
+
+#[derive(Clone, Default)]
+pub struct InMemoryCheckpointStore {
+    checkpoints: Arc<Mutex<HashMap<String, Position>>>,
+}
+
+impl InMemoryCheckpointStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl CheckpointStore for InMemoryCheckpointStore {
+    type Error = std::convert::Infallible;
+
+    async fn load(&self, name: &str) -> Result<Option<Position>, Self::Error> {
+        Ok(self.checkpoints.lock().unwrap().get(name).copied())
+    }
+
+    async fn save(&self, name: &str, position: Position) -> Result<(), Self::Error> {
+        self.checkpoints
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), position);
+        Ok(())
+    }
+
+    async fn clear(&self, name: &str) -> Result<(), Self::Error> {
+        self.checkpoints.lock().unwrap().remove(name);
+        Ok(())
+    }
+}
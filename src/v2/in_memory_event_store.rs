@@ -0,0 +1,1595 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+use super::event_envelope::recorded_at;
+use super::{
+    Aggregate, DeletableRepository, EnvelopeRepository, Event, EventEnvelope, EventLog,
+    EventReader, ExpiryPolicy, IdempotentRepository, MultiStreamRepository, Outbox, OutboxDisabled,
+    OutboxEntry, Position, RecordedDeletion, RecordedEvent, Repository, RepositoryError,
+    StreamAppend, Subscribe,
+};
+
+pub struct InMemoryEventStore<A: Aggregate> {
+    state: Arc<Mutex<State<A>>>,
+}
+
+struct State<A: Aggregate> {
+    // positions in `log` of the events of each stream
+    streams: HashMap<A::Id, Vec<usize>>,
+    // purged events leave a hole so that the positions of the others do not change, and
+    // deletions take a hole of their own
+    log: Vec<Option<EventEnvelope<A::Event>>>,
+    // streams deleted or purged, which accept no more events
+    deleted: HashSet<A::Id>,
+    // deleted or purged streams by the position of the deletion in `log`
+    deletions: BTreeMap<usize, (A::Id, bool)>,
+    // positions in `log` of the events not yet dispatched by an outbox relay, if enabled by
+    // `with_outbox`
+    outbox: Option<BTreeSet<usize>>,
+    // wakers of the subscriptions waiting for new events
+    wakers: HashMap<u64, Waker>,
+    next_subscription_id: u64,
+    // stream versions after the batches stored by `store_idempotent`, by stream and command id
+    command_ids: HashMap<(A::Id, String), Option<A::Version>>,
+    // command ids in the order they were recorded, for expiry
+    command_id_order: VecDeque<(Instant, (A::Id, String))>,
+    expiry_policy: ExpiryPolicy,
+    // the clock the age of the command ids is measured with, `Instant::now` unless set by
+    // `with_clock`
+    clock: Box<dyn Fn() -> Instant + Send + Sync>,
+}
+
+impl<A> State<A>
+where
+    A: Aggregate,
+    A::Id: Hash,
+{
+    fn stream(
+        &self,
+        id: &A::Id,
+    ) -> Option<impl DoubleEndedIterator<Item = &EventEnvelope<A::Event>>> {
+        self.streams.get(id).map(|positions| {
+            positions
+                .iter()
+                .filter_map(|position| self.log[*position].as_ref())
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn append(
+        &mut self,
+        id: &A::Id,
+        expected_version: Option<&A::Version>,
+        new_events: &[EventEnvelope<A::Event>],
+    ) -> Result<(), RepositoryError<A::Id, A::Version, A::Error>>
+    where
+        A::Event: Clone,
+        A::Id: Clone,
+        A::Version: Clone,
+    {
+        if new_events.is_empty() {
+            return Ok(());
+        }
+
+        self.check_not_deleted(id)?;
+        let actual_version = self.version(id);
+        check_version(id, expected_version, actual_version)?;
+        self.push(id, new_events);
+        self.wake();
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn check_not_deleted(
+        &self,
+        id: &A::Id,
+    ) -> Result<(), RepositoryError<A::Id, A::Version, A::Error>>
+    where
+        A::Id: Clone,
+    {
+        if self.deleted.contains(id) {
+            return Err(RepositoryError::Deleted { id: id.clone() });
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn delete(
+        &mut self,
+        id: &A::Id,
+        expected_version: &A::Version,
+    ) -> Result<(), RepositoryError<A::Id, A::Version, A::Error>>
+    where
+        A::Id: Clone,
+        A::Version: Clone,
+    {
+        self.check_not_deleted(id)?;
+        let actual_version = self.version(id);
+        check_version(id, Some(expected_version), actual_version)?;
+        self.deleted.insert(id.clone());
+        self.record_deletion(id, false);
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn purge(
+        &mut self,
+        id: &A::Id,
+        expected_version: &A::Version,
+    ) -> Result<(), RepositoryError<A::Id, A::Version, A::Error>>
+    where
+        A::Id: Clone,
+        A::Version: Clone,
+    {
+        let actual_version = self.version(id);
+        if actual_version.is_none() {
+            // already purged
+            self.check_not_deleted(id)?;
+        }
+        check_version(id, Some(expected_version), actual_version)?;
+        for position in self.streams.remove(id).unwrap_or_default() {
+            self.log[position] = None;
+            if let Some(outbox) = &mut self.outbox {
+                outbox.remove(&position);
+            }
+        }
+        self.deleted.insert(id.clone());
+        self.record_deletion(id, true);
+        Ok(())
+    }
+
+    fn record_deletion(&mut self, id: &A::Id, purged: bool)
+    where
+        A::Id: Clone,
+    {
+        self.deletions.insert(self.log.len(), (id.clone(), purged));
+        self.log.push(None);
+    }
+
+    fn version(&self, id: &A::Id) -> Option<A::Version> {
+        self.stream(id)
+            .and_then(|mut events| events.next_back())
+            .map(|envelope| envelope.event.version())
+    }
+
+    fn push(&mut self, id: &A::Id, new_events: &[EventEnvelope<A::Event>])
+    where
+        A::Event: Clone,
+        A::Id: Clone,
+    {
+        let recorded_at = recorded_at(
+            self.stream(id)
+                .and_then(|mut envelopes| envelopes.next_back())
+                .map(|envelope| envelope.recorded_at),
+        );
+        let positions = self.streams.entry(id.clone()).or_default();
+        for new_event in new_events {
+            positions.push(self.log.len());
+            if let Some(outbox) = &mut self.outbox {
+                outbox.insert(self.log.len());
+            }
+            self.log.push(Some(EventEnvelope {
+                recorded_at,
+                ..new_event.clone()
+            }));
+        }
+    }
+
+    fn wake(&mut self) {
+        for (_, waker) in self.wakers.drain() {
+            waker.wake();
+        }
+    }
+
+    fn expire_command_ids(&mut self) {
+        let now = (self.clock)();
+        while let Some((recorded_at, _)) = self.command_id_order.front()
+            && self.expiry_policy.is_expired(now - *recorded_at)
+        {
+            let (_, key) = self.command_id_order.pop_front().unwrap();
+            self.command_ids.remove(&key);
+        }
+    }
+}
+
+fn check_version<I, V, E>(
+    id: &I,
+    expected_version: Option<&V>,
+    actual_version: Option<V>,
+) -> Result<(), RepositoryError<I, V, E>>
+where
+    I: Clone,
+    V: Clone + Eq,
+{
+    match (expected_version, actual_version) {
+        (None, None) => {
+            // create
+            Ok(())
+        }
+        (None, Some(_)) => Err(RepositoryError::AlreadyExists { id: id.clone() }),
+        (Some(expected_version), Some(actual_version)) => {
+            // update
+            if actual_version != *expected_version {
+                return Err(RepositoryError::VersionConflict {
+                    expected: expected_version.clone(),
+                    actual: actual_version,
+                });
+            }
+            Ok(())
+        }
+        (Some(_), None) => Err(RepositoryError::NotFound { id: id.clone() }),
+    }
+}
+
+impl<A: Aggregate> InMemoryEventStore<A> {
+    pub fn new() -> Self {
+        Self::with_expiry_policy(ExpiryPolicy::Never)
+    }
+
+    pub fn with_expiry_policy(expiry_policy: ExpiryPolicy) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                streams: HashMap::new(),
+                log: vec![],
+                deleted: HashSet::new(),
+                deletions: BTreeMap::new(),
+                outbox: None,
+                wakers: HashMap::new(),
+                next_subscription_id: 0,
+                command_ids: HashMap::new(),
+                command_id_order: VecDeque::new(),
+                expiry_policy,
+                clock: Box::new(Instant::now),
+            })),
+        }
+    }
+
+    pub fn with_outbox(self) -> Self {
+        self.state.lock().unwrap().outbox.get_or_insert_default();
+        self
+    }
+
+    pub fn with_clock<C>(self, clock: C) -> Self
+    where
+        C: Fn() -> Instant + Send + Sync + 'static,
+    {
+        self.state.lock().unwrap().clock = Box::new(clock);
+        self
+    }
+}
+
+impl<A: Aggregate> Clone for InMemoryEventStore<A> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<A: Aggregate> Default for InMemoryEventStore<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> Repository for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Event: Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    type Aggregate = A;
+    type Error = RepositoryError<A::Id, A::Version, A::Error>;
+
+    async fn find(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<Option<Self::Aggregate>, Self::Error> {
+        let state = self.state.lock().unwrap();
+        if state.deleted.contains(id) {
+            return Ok(None);
+        }
+        let events = match state.stream(id) {
+            None => return Ok(None),
+            Some(events) => events
+                .map(|envelope| envelope.event.clone())
+                .collect::<Vec<_>>(),
+        };
+        drop(state);
+        A::replay(events)
+            .map(Some)
+            .map_err(RepositoryError::Backend)
+    }
+
+    async fn store(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[<Self::Aggregate as Aggregate>::Event],
+    ) -> Result<(), Self::Error> {
+        let new_events = new_events
+            .iter()
+            .cloned()
+            .map(EventEnvelope::new)
+            .collect::<Vec<_>>();
+        self.store_envelopes(id, expected_version, &new_events)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> EventReader for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Event: Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    async fn read_events(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        after_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+    ) -> Result<Vec<<Self::Aggregate as Aggregate>::Event>, Self::Error> {
+        let state = self.state.lock().unwrap();
+        let events = match state.stream(id) {
+            None => return Ok(vec![]),
+            Some(events) => events,
+        };
+        Ok(events
+            .map(|envelope| &envelope.event)
+            .filter(|event| {
+                after_version.is_none_or(|after_version| event.version() > *after_version)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn is_deleted(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.state.lock().unwrap().deleted.contains(id))
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> EnvelopeRepository for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Event: Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    async fn store_envelopes(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[EventEnvelope<<Self::Aggregate as Aggregate>::Event>],
+    ) -> Result<(), Self::Error> {
+        self.state
+            .lock()
+            .unwrap()
+            .append(id, expected_version, new_events)
+    }
+
+    async fn read_envelopes(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        after_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+    ) -> Result<Vec<EventEnvelope<<Self::Aggregate as Aggregate>::Event>>, Self::Error> {
+        let state = self.state.lock().unwrap();
+        let envelopes = match state.stream(id) {
+            None => return Ok(vec![]),
+            Some(envelopes) => envelopes,
+        };
+        Ok(envelopes
+            .filter(|envelope| {
+                after_version.is_none_or(|after_version| envelope.event.version() > *after_version)
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> MultiStreamRepository for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Event: Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    async fn store_many(
+        &self,
+        appends: &[StreamAppend<
+            <Self::Aggregate as Aggregate>::Id,
+            <Self::Aggregate as Aggregate>::Version,
+            <Self::Aggregate as Aggregate>::Event,
+        >],
+    ) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        // versions of the streams once the earlier appends of the batch are applied
+        let mut versions = HashMap::new();
+        for append in appends {
+            let Some(last_event) = append.events.last() else {
+                continue;
+            };
+            state.check_not_deleted(&append.id)?;
+            let actual_version = versions
+                .get(&append.id)
+                .cloned()
+                .or_else(|| state.version(&append.id));
+            check_version(&append.id, append.expected_version.as_ref(), actual_version)?;
+            versions.insert(&append.id, last_event.version());
+        }
+
+        for append in appends {
+            let new_events = append
+                .events
+                .iter()
+                .cloned()
+                .map(EventEnvelope::new)
+                .collect::<Vec<_>>();
+            state.push(&append.id, &new_events);
+        }
+        if !versions.is_empty() {
+            state.wake();
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> IdempotentRepository for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Event: Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    async fn store_idempotent(
+        &self,
+        command_id: &str,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[<Self::Aggregate as Aggregate>::Event],
+    ) -> Result<Option<<Self::Aggregate as Aggregate>::Version>, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        state.expire_command_ids();
+        state.check_not_deleted(id)?;
+        let key = (id.clone(), command_id.to_owned());
+        if let Some(version) = state.command_ids.get(&key) {
+            return Ok(version.clone());
+        }
+
+        let new_events = new_events
+            .iter()
+            .cloned()
+            .map(EventEnvelope::new)
+            .collect::<Vec<_>>();
+        state.append(id, expected_version, &new_events)?;
+        let version = state.version(id);
+        state.command_ids.insert(key.clone(), version.clone());
+        let recorded_at = (state.clock)();
+        state.command_id_order.push_back((recorded_at, key));
+        Ok(version)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> DeletableRepository for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Event: Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    async fn delete(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        self.state.lock().unwrap().delete(id, expected_version)
+    }
+
+    async fn purge(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        self.state.lock().unwrap().purge(id, expected_version)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> EventLog for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Event: Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    async fn read_all(
+        &self,
+        from_position: Position,
+        limit: usize,
+    ) -> Result<Vec<RecordedEvent<<Self::Aggregate as Aggregate>::Event>>, Self::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .log
+            .iter()
+            .enumerate()
+            .skip(usize::try_from(from_position.0).unwrap_or(usize::MAX))
+            .filter_map(|(position, envelope)| {
+                envelope.as_ref().map(|envelope| RecordedEvent {
+                    position: Position(position as u64),
+                    event: envelope.event.clone(),
+                })
+            })
+            .take(limit)
+            .collect())
+    }
+    async fn read_deletions(
+        &self,
+        from_position: Position,
+        limit: usize,
+    ) -> Result<Vec<RecordedDeletion<<Self::Aggregate as Aggregate>::Id>>, Self::Error> {
+        let state = self.state.lock().unwrap();
+        let from_position = usize::try_from(from_position.0).unwrap_or(usize::MAX);
+        Ok(state
+            .deletions
+            .range(from_position..)
+            .take(limit)
+            .map(|(position, (id, purged))| RecordedDeletion {
+                position: Position(*position as u64),
+                id: id.clone(),
+                purged: *purged,
+            })
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> Outbox for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Event: Clone + Send + Sync,
+    A::Id: Send + Sync,
+    A::Version: Send + Sync,
+{
+    type Event = A::Event;
+    type Error = OutboxDisabled;
+
+    async fn pending(&self, limit: usize) -> Result<Vec<OutboxEntry<Self::Event>>, Self::Error> {
+        let state = self.state.lock().unwrap();
+        let outbox = state.outbox.as_ref().ok_or(OutboxDisabled)?;
+        Ok(outbox
+            .iter()
+            .take(limit)
+            .filter_map(|position| {
+                state.log[*position].clone().map(|envelope| OutboxEntry {
+                    sequence: *position as u64,
+                    envelope,
+                })
+            })
+            .collect())
+    }
+
+    async fn mark_dispatched(&self, sequences: &[u64]) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let outbox = state.outbox.as_mut().ok_or(OutboxDisabled)?;
+        for sequence in sequences {
+            if let Ok(position) = usize::try_from(*sequence) {
+                outbox.remove(&position);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<A> Subscribe for InMemoryEventStore<A>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + Sync + 'static,
+    A::Event: Clone + Send + Sync,
+    A::Id: Clone + Debug + Hash + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+{
+    type Subscription = InMemorySubscription<A>;
+
+    fn subscribe(&self, from_position: Position) -> Self::Subscription {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_subscription_id;
+        state.next_subscription_id += 1;
+        InMemorySubscription {
+            state: Arc::clone(&self.state),
+            id,
+            position: from_position,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+// Events are read from the log only when the subscriber polls for the next one, so a slow
+// subscriber never causes events to be buffered on its behalf.
+pub struct InMemorySubscription<A: Aggregate> {
+    state: Arc<Mutex<State<A>>>,
+    id: u64,
+    position: Position,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<A: Aggregate> InMemorySubscription<A> {
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle<A> {
+        CancelHandle {
+            state: Arc::clone(&self.state),
+            id: self.id,
+            cancelled: Arc::clone(&self.cancelled),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel_handle().cancel();
+    }
+}
+
+impl<A: Aggregate> Drop for InMemorySubscription<A> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.wakers.remove(&self.id);
+        }
+    }
+}
+
+impl<A> futures_core::Stream for InMemorySubscription<A>
+where
+    A: Aggregate,
+    A::Event: Clone,
+{
+    type Item = Result<RecordedEvent<A::Event>, RepositoryError<A::Id, A::Version, A::Error>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.state.lock().unwrap();
+        // checked under the lock so that a concurrent cancel cannot miss the registered waker
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Poll::Ready(None);
+        }
+        // skips the holes left by purged events
+        let event = usize::try_from(self.position.0).ok().and_then(|from| {
+            state
+                .log
+                .iter()
+                .enumerate()
+                .skip(from)
+                .find_map(|(position, envelope)| {
+                    envelope
+                        .as_ref()
+                        .map(|envelope| (Position(position as u64), envelope.event.clone()))
+                })
+        });
+        match event {
+            None => {
+                state.wakers.insert(self.id, cx.waker().clone());
+                Poll::Pending
+            }
+            Some((position, event)) => {
+                drop(state);
+                self.position = position.next();
+                Poll::Ready(Some(Ok(RecordedEvent { position, event })))
+            }
+        }
+    }
+}
+
+pub struct CancelHandle<A: Aggregate> {
+    state: Arc<Mutex<State<A>>>,
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<A: Aggregate> Clone for CancelHandle<A> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+            id: self.id,
+            cancelled: Arc::clone(&self.cancelled),
+        }
+    }
+}
+
+impl<A: Aggregate> CancelHandle<A> {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let waker = self.state.lock().unwrap().wakers.remove(&self.id);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use super::*;
+    use crate::v2::Direction;
+    use crate::v2::testing::repository_conformance;
+    use crate::v2::tests::{
+        AggregateEvent, AggregateId, AggregateImpl, AggregateVersion, Fixture, create, created,
+    };
+
+    #[tokio::test]
+    async fn test_find_and_store() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+
+        let aggregate = AggregateImpl::create();
+        let id = aggregate.id();
+        let version = aggregate.version();
+
+        assert!(repository.find(&id).await.unwrap().is_none());
+
+        repository
+            .store(&id, None, &[created(&id.0, version.0)])
+            .await
+            .unwrap();
+
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.id(), id);
+        assert_eq!(found_aggregate.version(), version);
+
+        let (updated_aggregate, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id, Some(&found_aggregate.version()), &events)
+            .await
+            .unwrap();
+
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), updated_aggregate.version());
+    }
+
+    #[tokio::test]
+    async fn test_store_rejects_conflicts() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(1)), &[created("1", 1)])
+                .await,
+            Err(RepositoryError::NotFound { .. })
+        ));
+
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository.store(&id, None, &[created("1", 1)]).await,
+            Err(RepositoryError::AlreadyExists { .. })
+        ));
+
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        let (_, events) = found_aggregate.update().unwrap();
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(2)), &events)
+                .await,
+            Err(RepositoryError::VersionConflict {
+                expected: AggregateVersion(2),
+                actual: AggregateVersion(1),
+            })
+        ));
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(1)), &events)
+                .await,
+            Err(RepositoryError::VersionConflict {
+                expected: AggregateVersion(1),
+                actual: AggregateVersion(2),
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_store_empty_events() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        repository.store(&id, None, &[]).await.unwrap();
+
+        assert!(repository.find(&id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_read_events() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        assert!(repository.read_events(&id, None).await.unwrap().is_empty());
+
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        let (_, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+
+        let versions =
+            |events: Vec<AggregateEvent>| events.iter().map(Event::version).collect::<Vec<_>>();
+        assert_eq!(
+            versions(repository.read_events(&id, None).await.unwrap()),
+            vec![AggregateVersion(1), AggregateVersion(2)]
+        );
+        assert_eq!(
+            versions(
+                repository
+                    .read_events(&id, Some(&AggregateVersion(1)))
+                    .await
+                    .unwrap()
+            ),
+            vec![AggregateVersion(2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_envelopes() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        let mut envelope = EventEnvelope::new(created("1", 1))
+            .with_actor("alice")
+            .with_header("trace-id", "abc");
+        // the store records its own time
+        envelope.recorded_at = std::time::SystemTime::UNIX_EPOCH;
+        let before = std::time::SystemTime::now();
+
+        repository
+            .store_envelopes(&id, None, std::slice::from_ref(&envelope))
+            .await
+            .unwrap();
+        let (_, events) = repository
+            .find(&id)
+            .await
+            .unwrap()
+            .unwrap()
+            .update()
+            .unwrap();
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+
+        let envelopes = repository.read_envelopes(&id, None).await.unwrap();
+        assert_eq!(envelopes.len(), 2);
+        assert!(envelopes[0].recorded_at >= before);
+        assert!(envelopes[1].recorded_at >= envelopes[0].recorded_at);
+        assert_eq!(
+            envelopes[0],
+            EventEnvelope {
+                recorded_at: envelopes[0].recorded_at,
+                ..envelope
+            }
+        );
+        assert_eq!(envelopes[1].event, events[0]);
+        assert!(envelopes[1].actor.is_none());
+        assert_eq!(
+            repository
+                .read_envelopes(&id, Some(&AggregateVersion(1)))
+                .await
+                .unwrap(),
+            envelopes[1..]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_idempotent() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        for _ in 0..2 {
+            assert_eq!(
+                repository
+                    .store_idempotent("a", &id, None, &[created("1", 1)])
+                    .await
+                    .unwrap(),
+                Some(AggregateVersion(1))
+            );
+        }
+        assert_eq!(repository.read_events(&id, None).await.unwrap().len(), 1);
+
+        // a rejected batch is not remembered
+        let (_, events) = repository
+            .find(&id)
+            .await
+            .unwrap()
+            .unwrap()
+            .update()
+            .unwrap();
+        assert!(matches!(
+            repository
+                .store_idempotent("b", &id, Some(&AggregateVersion(2)), &events)
+                .await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+        assert_eq!(
+            repository
+                .store_idempotent("b", &id, Some(&AggregateVersion(1)), &events)
+                .await
+                .unwrap(),
+            Some(AggregateVersion(2))
+        );
+        assert_eq!(
+            repository
+                .store_idempotent("a", &id, None, &[created("1", 1)])
+                .await
+                .unwrap(),
+            Some(AggregateVersion(1))
+        );
+
+        // command ids are scoped to their stream
+        let other_id = AggregateId("2".to_owned());
+        assert_eq!(
+            repository
+                .store_idempotent("a", &other_id, None, &[created("2", 1)])
+                .await
+                .unwrap(),
+            Some(AggregateVersion(1))
+        );
+        assert_eq!(
+            repository.read_events(&other_id, None).await.unwrap().len(),
+            1
+        );
+
+        // a deleted stream does not report its batches as stored
+        repository.delete(&id, &AggregateVersion(2)).await.unwrap();
+        assert!(matches!(
+            repository
+                .store_idempotent("a", &id, None, &[created("1", 1)])
+                .await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_store_idempotent_expiry() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let advance = |duration| *now.lock().unwrap() += duration;
+        let repository = InMemoryEventStore::<AggregateImpl>::with_expiry_policy(
+            ExpiryPolicy::After(std::time::Duration::from_secs(60)),
+        )
+        .with_clock({
+            let now = Arc::clone(&now);
+            move || *now.lock().unwrap()
+        });
+        let id = AggregateId("1".to_owned());
+        repository
+            .store_idempotent("a", &id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+
+        advance(std::time::Duration::from_secs(30));
+        assert!(
+            repository
+                .store_idempotent("a", &id, None, &[created("1", 1)])
+                .await
+                .is_ok()
+        );
+
+        advance(std::time::Duration::from_secs(30));
+        assert!(matches!(
+            repository
+                .store_idempotent("a", &id, None, &[created("1", 1)])
+                .await,
+            Err(RepositoryError::AlreadyExists { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_store_many() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id1 = AggregateId("1".to_owned());
+        let id2 = AggregateId("2".to_owned());
+        repository
+            .store(&id1, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let (_, events1) = repository
+            .find(&id1)
+            .await
+            .unwrap()
+            .unwrap()
+            .update()
+            .unwrap();
+
+        repository
+            .store_many(&[
+                StreamAppend::new(id1.clone(), Some(AggregateVersion(1)), events1.clone()),
+                StreamAppend::new(id2.clone(), None, vec![created("2", 1)]),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            repository.find(&id1).await.unwrap().unwrap().version(),
+            AggregateVersion(2)
+        );
+        assert_eq!(
+            repository.find(&id2).await.unwrap().unwrap().version(),
+            AggregateVersion(1)
+        );
+
+        // the stale expected version of the second stream rejects the whole batch
+        let (_, events1) = repository
+            .find(&id1)
+            .await
+            .unwrap()
+            .unwrap()
+            .update()
+            .unwrap();
+        let (_, events2) = repository
+            .find(&id2)
+            .await
+            .unwrap()
+            .unwrap()
+            .update()
+            .unwrap();
+        assert!(matches!(
+            repository
+                .store_many(&[
+                    StreamAppend::new(id1.clone(), Some(AggregateVersion(2)), events1),
+                    StreamAppend::new(id2.clone(), Some(AggregateVersion(2)), events2),
+                ])
+                .await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+        assert_eq!(repository.read_all(Position(0), 10).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_store_many_same_stream() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        let (_, events) = AggregateImpl::create().update().unwrap();
+
+        repository
+            .store_many(&[
+                StreamAppend::new(id.clone(), None, vec![created("1", 1)]),
+                StreamAppend::new(id.clone(), Some(AggregateVersion(1)), events),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            repository.find(&id).await.unwrap().unwrap().version(),
+            AggregateVersion(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_stream() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        for version in 1..5 {
+            let (_, events) = repository
+                .find(&id)
+                .await
+                .unwrap()
+                .unwrap()
+                .update()
+                .unwrap();
+            repository
+                .store(&id, Some(&AggregateVersion(version)), &events)
+                .await
+                .unwrap();
+        }
+        let versions = |events: Vec<AggregateEvent>| {
+            events
+                .iter()
+                .map(|event| event.version().0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            versions(
+                repository
+                    .read_stream(
+                        &id,
+                        Bound::Included(&AggregateVersion(2)),
+                        Bound::Included(&AggregateVersion(4)),
+                        Direction::Forward,
+                        10,
+                    )
+                    .await
+                    .unwrap()
+            ),
+            vec![2, 3, 4]
+        );
+
+        // page backward from the end
+        let page = repository
+            .read_stream(
+                &id,
+                Bound::Unbounded,
+                Bound::Unbounded,
+                Direction::Backward,
+                2,
+            )
+            .await
+            .unwrap();
+        let last = page.last().unwrap().version();
+        assert_eq!(versions(page), vec![5, 4]);
+        assert_eq!(
+            versions(
+                repository
+                    .read_stream(
+                        &id,
+                        Bound::Unbounded,
+                        Bound::Excluded(&last),
+                        Direction::Backward,
+                        2,
+                    )
+                    .await
+                    .unwrap()
+            ),
+            vec![3, 2]
+        );
+
+        // page forward
+        assert_eq!(
+            versions(
+                repository
+                    .read_stream(
+                        &id,
+                        Bound::Excluded(&AggregateVersion(3)),
+                        Bound::Unbounded,
+                        Direction::Forward,
+                        10,
+                    )
+                    .await
+                    .unwrap()
+            ),
+            vec![4, 5]
+        );
+        assert!(
+            repository
+                .read_stream(
+                    &AggregateId("2".to_owned()),
+                    Bound::Unbounded,
+                    Bound::Unbounded,
+                    Direction::Forward,
+                    10,
+                )
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_at() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        let before = std::time::SystemTime::now();
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let between = std::time::SystemTime::now();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let (_, events) = repository
+            .find(&id)
+            .await
+            .unwrap()
+            .unwrap()
+            .update()
+            .unwrap();
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repository
+                .find_at(&id, &AggregateVersion(1))
+                .await
+                .unwrap()
+                .unwrap()
+                .version(),
+            AggregateVersion(1)
+        );
+        assert_eq!(
+            repository
+                .find_at(&id, &AggregateVersion(9))
+                .await
+                .unwrap()
+                .unwrap()
+                .version(),
+            AggregateVersion(2)
+        );
+        assert!(
+            repository
+                .find_at(&id, &AggregateVersion(0))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        assert_eq!(
+            repository
+                .find_at_time(&id, between)
+                .await
+                .unwrap()
+                .unwrap()
+                .version(),
+            AggregateVersion(1)
+        );
+        assert_eq!(
+            repository
+                .find_at_time(&id, std::time::SystemTime::now())
+                .await
+                .unwrap()
+                .unwrap()
+                .version(),
+            AggregateVersion(2)
+        );
+        assert!(
+            repository
+                .find_at_time(&id, before - std::time::Duration::from_secs(1))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_at_time_ignores_forged_times() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        let mut envelope = EventEnvelope::new(created("1", 1));
+        envelope.recorded_at = std::time::SystemTime::now() + std::time::Duration::from_secs(3600);
+        repository
+            .store_envelopes(&id, None, &[envelope])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repository
+                .find_at_time(&id, std::time::SystemTime::now())
+                .await
+                .unwrap()
+                .unwrap()
+                .version(),
+            AggregateVersion(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_at_deleted() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        repository.delete(&id, &AggregateVersion(1)).await.unwrap();
+
+        assert!(
+            repository
+                .find_at(&id, &AggregateVersion(1))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repository
+                .find_at_time(&id, std::time::SystemTime::now())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(repository.read_events(&id, None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        assert!(matches!(
+            repository.delete(&id, &AggregateVersion(1)).await,
+            Err(RepositoryError::NotFound { .. })
+        ));
+
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository.delete(&id, &AggregateVersion(2)).await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+        repository.delete(&id, &AggregateVersion(1)).await.unwrap();
+        assert!(repository.is_deleted(&id).await.unwrap());
+
+        assert!(repository.find(&id).await.unwrap().is_none());
+        assert_eq!(repository.read_events(&id, None).await.unwrap().len(), 1);
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(1)), &[created("1", 2)])
+                .await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+        assert!(matches!(
+            repository.store(&id, None, &[created("1", 1)]).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+        assert!(matches!(
+            repository
+                .store_many(&[StreamAppend::new(
+                    id.clone(),
+                    Some(AggregateVersion(1)),
+                    vec![created("1", 2)],
+                )])
+                .await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+        assert!(matches!(
+            repository.delete(&id, &AggregateVersion(1)).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        use futures::{FutureExt as _, StreamExt as _};
+
+        let repository = InMemoryEventStore::<AggregateImpl>::new().with_outbox();
+        let id1 = AggregateId("1".to_owned());
+        let id2 = AggregateId("2".to_owned());
+        repository
+            .store(&id1, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        repository
+            .store(&id2, None, &[created("2", 1)])
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repository.purge(&id1, &AggregateVersion(2)).await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+        repository.delete(&id1, &AggregateVersion(1)).await.unwrap();
+        repository.purge(&id1, &AggregateVersion(1)).await.unwrap();
+
+        assert!(repository.find(&id1).await.unwrap().is_none());
+        assert!(repository.read_events(&id1, None).await.unwrap().is_empty());
+        assert!(matches!(
+            repository.store(&id1, None, &[created("1", 1)]).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+        assert!(matches!(
+            repository.purge(&id1, &AggregateVersion(1)).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+
+        // the positions of the other events do not change
+        let recorded = repository.read_all(Position(0), 10).await.unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].position, Position(1));
+        let mut subscription = repository.subscribe(Position(0));
+        assert_eq!(
+            subscription.next().await.unwrap().unwrap().position,
+            Position(1)
+        );
+        assert!(subscription.next().now_or_never().is_none());
+        let pending = repository.pending(10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].envelope.event.id(), id2);
+    }
+
+    #[tokio::test]
+    async fn test_read_deletions() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id1 = AggregateId("1".to_owned());
+        let id2 = AggregateId("2".to_owned());
+        create(&repository, "1").await;
+        create(&repository, "2").await;
+        repository.delete(&id1, &AggregateVersion(1)).await.unwrap();
+        repository.purge(&id2, &AggregateVersion(1)).await.unwrap();
+        create(&repository, "3").await;
+
+        assert_eq!(
+            repository.read_deletions(Position(0), 10).await.unwrap(),
+            vec![
+                RecordedDeletion {
+                    position: Position(2),
+                    id: id1,
+                    purged: false,
+                },
+                RecordedDeletion {
+                    position: Position(3),
+                    id: id2.clone(),
+                    purged: true,
+                },
+            ]
+        );
+        assert_eq!(
+            repository.read_deletions(Position(3), 1).await.unwrap(),
+            vec![RecordedDeletion {
+                position: Position(3),
+                id: id2,
+                purged: true,
+            }]
+        );
+        assert_eq!(
+            repository
+                .read_all(Position(0), 10)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|recorded| recorded.position)
+                .collect::<Vec<_>>(),
+            vec![Position(0), Position(4)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        repository_conformance(&Fixture(InMemoryEventStore::<AggregateImpl>::new)).await;
+    }
+
+    #[tokio::test]
+    async fn test_read_all() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id1 = AggregateId("1".to_owned());
+        let id2 = AggregateId("2".to_owned());
+
+        assert!(
+            repository
+                .read_all(Position(0), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        repository
+            .store(&id1, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        repository
+            .store(&id2, None, &[created("2", 1)])
+            .await
+            .unwrap();
+        let found_aggregate = repository.find(&id1).await.unwrap().unwrap();
+        let (_, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id1, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+
+        let recorded = repository.read_all(Position(0), 10).await.unwrap();
+        assert_eq!(
+            recorded
+                .iter()
+                .map(|it| (it.position, it.event.id(), it.event.version()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Position(0), id1.clone(), AggregateVersion(1)),
+                (Position(1), id2.clone(), AggregateVersion(1)),
+                (Position(2), id1.clone(), AggregateVersion(2)),
+            ]
+        );
+
+        let recorded = repository.read_all(Position(1), 1).await.unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].position, Position(1));
+        assert!(
+            repository
+                .read_all(Position(3), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        use futures::{FutureExt as _, StreamExt as _};
+
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id1 = AggregateId("1".to_owned());
+        let id2 = AggregateId("2".to_owned());
+        repository
+            .store(&id1, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        repository
+            .store(&id2, None, &[created("2", 1)])
+            .await
+            .unwrap();
+
+        let mut subscription = repository.subscribe(Position(1));
+
+        // catch up
+        let recorded = subscription.next().await.unwrap().unwrap();
+        assert_eq!(recorded.position, Position(1));
+        assert_eq!(recorded.event.id(), id2);
+        assert!(subscription.next().now_or_never().is_none());
+
+        // live
+        let found_aggregate = repository.find(&id1).await.unwrap().unwrap();
+        let (_, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id1, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+        let recorded = subscription.next().await.unwrap().unwrap();
+        assert_eq!(recorded.position, Position(2));
+        assert_eq!(recorded.event.version(), AggregateVersion(2));
+        assert_eq!(subscription.position(), Position(3));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_cancel() {
+        use futures::{FutureExt as _, StreamExt as _};
+
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+
+        let mut subscription = repository.subscribe(Position(0));
+        let cancel_handle = subscription.cancel_handle();
+        assert!(subscription.next().await.is_some());
+        assert!(subscription.next().now_or_never().is_none());
+
+        cancel_handle.cancel();
+        assert!(subscription.next().await.is_none());
+        assert!(repository.state.lock().unwrap().wakers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clone_shares_streams() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let cloned = repository.clone();
+        let id = AggregateId("1".to_owned());
+
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+
+        assert!(cloned.find(&id).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::{Arc, Mutex};
+
+use super::{EventEnvelope, Publisher};
+
+pub struct InMemoryPublisher<E> {
+    published: Arc<Mutex<Vec<EventEnvelope<E>>>>,
+}
+
+impl<E> InMemoryPublisher<E> {
+    pub fn new() -> Self {
+        Self {
+            published: Arc::new(Mutex::new(vec![])),
+        }
+    }
+}
+
+impl<E: Clone> InMemoryPublisher<E> {
+    pub fn published(&self) -> Vec<EventEnvelope<E>> {
+        self.published.lock().unwrap().clone()
+    }
+}
+
+impl<E> Clone for InMemoryPublisher<E> {
+    fn clone(&self) -> Self {
+        Self {
+            published: Arc::clone(&self.published),
+        }
+    }
+}
+
+impl<E> Default for InMemoryPublisher<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl<E> Publisher for InMemoryPublisher<E>
+where
+    E: Clone + Send + Sync,
+{
+    type Event = E;
+    type Error = std::convert::Infallible;
+
+    async fn publish(&self, envelope: &EventEnvelope<Self::Event>) -> Result<(), Self::Error> {
+        self.published.lock().unwrap().push(envelope.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use super::{Aggregate, Snapshot, SnapshotStore};
+
+pub struct InMemorySnapshotStore<A: Snapshot> {
+    snapshots: Arc<Mutex<HashMap<A::Id, Entry<A>>>>,
+}
+
+type Entry<A> = (<A as Aggregate>::Version, <A as Snapshot>::State);
+
+impl<A: Snapshot> InMemorySnapshotStore<A> {
+    pub fn new() -> Self {
+        Self {
+            snapshots: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<A: Snapshot> Clone for InMemorySnapshotStore<A> {
+    fn clone(&self) -> Self {
+        Self {
+            snapshots: Arc::clone(&self.snapshots),
+        }
+    }
+}
+
+impl<A: Snapshot> Default for InMemorySnapshotStore<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl<A> SnapshotStore for InMemorySnapshotStore<A>
+where
+    A: Snapshot + Send + Sync,
+    A::Id: Hash + Send + Sync,
+    A::State: Clone + Send + Sync,
+    A::Version: Clone + Send + Sync,
+{
+    type Aggregate = A;
+    type Error = std::convert::Infallible;
+
+    async fn load(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<Option<Entry<A>>, Self::Error> {
+        Ok(self.snapshots.lock().unwrap().get(id).cloned())
+    }
+
+    async fn save(&self, aggregate: &Self::Aggregate) -> Result<(), Self::Error> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let version = aggregate.version();
+        if snapshots
+            .get(&aggregate.id())
+            .is_some_and(|(saved_version, _)| *saved_version >= version)
+        {
+            return Ok(());
+        }
+        snapshots.insert(aggregate.id(), (version, aggregate.snapshot()));
+        Ok(())
+    }
+
+    async fn remove(&self, id: &<Self::Aggregate as Aggregate>::Id) -> Result<(), Self::Error> {
+        self.snapshots.lock().unwrap().remove(id);
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use super::event_codec::check_schema;
+use super::{EncodedEvent, EventCodec, EventSchema};
+
+pub struct JsonCodec<E> {
+    _event: std::marker::PhantomData<fn() -> E>,
+}
+
+impl<E> JsonCodec<E> {
+    pub fn new() -> Self {
+        Self {
+            _event: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<E> Default for JsonCodec<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> EventCodec for JsonCodec<E>
+where
+    E: EventSchema + serde::Serialize + serde::de::DeserializeOwned,
+{
+    type Event = E;
+    type Error = serde_json::Error;
+
+    fn encode(&self, event: &Self::Event) -> Result<EncodedEvent, Self::Error> {
+        Ok(EncodedEvent {
+            event_type: event.event_type().to_owned(),
+            schema_version: event.schema_version(),
+            payload: serde_json::to_vec(event)?,
+        })
+    }
+
+    fn decode(&self, encoded: &EncodedEvent) -> Result<Self::Event, Self::Error> {
+        check_schema(serde_json::from_slice(&encoded.payload)?, encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+    enum OrderEvent {
+        Placed { id: String, version: u16 },
+        Shipped { id: String, version: u16 },
+    }
+
+    impl EventSchema for OrderEvent {
+        fn event_type(&self) -> &'static str {
+            match self {
+                Self::Placed { .. } => "OrderPlaced",
+                Self::Shipped { .. } => "OrderShipped",
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let codec = JsonCodec::<OrderEvent>::new();
+        let event = OrderEvent::Placed {
+            id: "1".to_owned(),
+            version: 1,
+        };
+
+        let encoded = codec.encode(&event).unwrap();
+        assert_eq!(encoded.event_type, "OrderPlaced");
+        assert_eq!(encoded.schema_version, 1);
+        assert_eq!(
+            String::from_utf8(encoded.payload.clone()).unwrap(),
+            r#"{"Placed":{"id":"1","version":1}}"#
+        );
+        assert_eq!(codec.decode(&encoded).unwrap(), event);
+    }
+
+    #[test]
+    fn test_decode_invalid_payload() {
+        let codec = JsonCodec::<OrderEvent>::new();
+        assert!(
+            codec
+                .decode(&EncodedEvent {
+                    event_type: "OrderPlaced".to_owned(),
+                    schema_version: 1,
+                    payload: b"{}".to_vec(),
+                })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_decode_mismatched_event_type() {
+        let codec = JsonCodec::<OrderEvent>::new();
+        let error = codec
+            .decode(&EncodedEvent {
+                event_type: "OrderShipped".to_owned(),
+                schema_version: 1,
+                payload: br#"{"Placed":{"id":"1","version":1}}"#.to_vec(),
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected event type (expected = OrderShipped, actual = OrderPlaced)"
+        );
+    }
+
+    #[test]
+    fn test_decode_undeclared_schema_version() {
+        let codec = JsonCodec::<OrderEvent>::new();
+        let error = codec
+            .decode(&EncodedEvent {
+                event_type: "OrderPlaced".to_owned(),
+                schema_version: 2,
+                payload: br#"{"Placed":{"id":"1","version":1}}"#.to_vec(),
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected schema version for OrderPlaced (expected = 2, actual = 1)"
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use super::EventEnvelope;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutboxEntry<E> {
+    pub sequence: u64,
+    pub envelope: EventEnvelope<E>,
+}
+
+// Stores record their events for an outbox relay only once enabled with `with_outbox`, and
+// must then have them dispatched. Until then, `pending` and `mark_dispatched` fail.
+#[async_trait::async_trait]
+pub trait Outbox {
+    type Event;
+    type Error: std::error::Error;
+
+    async fn pending(&self, limit: usize) -> Result<Vec<OutboxEntry<Self::Event>>, Self::Error>;
+
+    async fn mark_dispatched(&self, sequences: &[u64]) -> Result<(), Self::Error>;
+}
+
+#[async_trait::async_trait]
+pub trait Publisher {
+    type Event;
+    type Error: std::error::Error;
+
+    async fn publish(&self, envelope: &EventEnvelope<Self::Event>) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug)]
+pub struct OutboxDisabled;
+
+impl std::fmt::Display for OutboxDisabled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Outbox not enabled")
+    }
+}
+
+impl std::error::Error for OutboxDisabled {}
+
+#[derive(Debug)]
+pub enum OutboxError<O, P> {
+    Outbox(O),
+    Publisher(P),
+}
+
+impl<O, P> std::fmt::Display for OutboxError<O, P>
+where
+    O: std::fmt::Display,
+    P: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Outbox(e) => std::fmt::Display::fmt(e, f),
+            Self::Publisher(e) => std::fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<O, P> std::error::Error for OutboxError<O, P>
+where
+    O: std::error::Error + 'static,
+    P: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Outbox(e) => Some(e),
+            Self::Publisher(e) => Some(e),
+        }
+    }
+}
+
+// Entries are marked dispatched only after they were published, so an entry whose
+// publication succeeded right before a crash is published again on the next run.
+pub struct OutboxRelay<O, P> {
+    outbox: O,
+    publisher: P,
+    batch_size: usize,
+}
+
+impl<O, P> OutboxRelay<O, P>
+where
+    O: Outbox,
+    P: Publisher<Event = O::Event>,
+{
+    pub fn new(outbox: O, publisher: P, batch_size: usize) -> Self {
+        Self {
+            outbox,
+            publisher,
+            // an empty batch would look dispatched without reading anything
+            batch_size: batch_size.max(1),
+        }
+    }
+
+    pub fn publisher(&self) -> &P {
+        &self.publisher
+    }
+
+    pub async fn run_once(&self) -> Result<usize, OutboxError<O::Error, P::Error>> {
+        let entries = self
+            .outbox
+            .pending(self.batch_size)
+            .await
+            .map_err(OutboxError::Outbox)?;
+        let mut dispatched = vec![];
+        let mut published = Ok(());
+        for entry in &entries {
+            if let Err(e) = self.publisher.publish(&entry.envelope).await {
+                published = Err(OutboxError::Publisher(e));
+                break;
+            }
+            dispatched.push(entry.sequence);
+        }
+        // entries split by an upcaster share a sequence and must all be published first
+        if published.is_err()
+            && let Some(failed) = entries.get(dispatched.len())
+        {
+            dispatched.retain(|sequence| *sequence != failed.sequence);
+        }
+        dispatched.dedup();
+        if !dispatched.is_empty() {
+            self.outbox
+                .mark_dispatched(&dispatched)
+                .await
+                .map_err(OutboxError::Outbox)?;
+        }
+        published.map(|()| entries.len())
+    }
+
+    pub async fn run_until_empty(&self) -> Result<usize, OutboxError<O::Error, P::Error>> {
+        let mut total = 0;
+        loop {
+            match self.run_once().await? {
+                0 => return Ok(total),
+                n => total += n,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::v2::tests::{AggregateEvent, AggregateId, AggregateImpl, create};
+    use crate::v2::{Event, InMemoryEventStore, InMemoryPublisher};
+
+    // fails every publication after the first `remaining` ones
+    struct FlakyPublisher {
+        inner: InMemoryPublisher<AggregateEvent>,
+        remaining: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Publisher for FlakyPublisher {
+        type Event = AggregateEvent;
+        type Error = std::io::Error;
+
+        async fn publish(&self, envelope: &EventEnvelope<Self::Event>) -> Result<(), Self::Error> {
+            if self
+                .remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_err()
+            {
+                return Err(std::io::Error::other("Broker unavailable"));
+            }
+            self.inner.publish(envelope).await.unwrap();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relay() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new().with_outbox();
+        let publisher = InMemoryPublisher::new();
+        let relay = OutboxRelay::new(repository.clone(), publisher.clone(), 2);
+
+        create(&repository, "1").await;
+        create(&repository, "2").await;
+        create(&repository, "3").await;
+        assert_eq!(relay.run_until_empty().await.unwrap(), 3);
+        assert_eq!(relay.run_once().await.unwrap(), 0);
+        assert_eq!(
+            publisher
+                .published()
+                .into_iter()
+                .map(|envelope| envelope.event.id())
+                .collect::<Vec<_>>(),
+            vec![
+                AggregateId("1".to_owned()),
+                AggregateId("2".to_owned()),
+                AggregateId("3".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_relay_redelivers_after_failure() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new().with_outbox();
+        let publisher = FlakyPublisher {
+            inner: InMemoryPublisher::new(),
+            remaining: AtomicUsize::new(1),
+        };
+        let relay = OutboxRelay::new(repository.clone(), publisher, 10);
+
+        create(&repository, "1").await;
+        create(&repository, "2").await;
+        assert!(matches!(
+            relay.run_once().await,
+            Err(OutboxError::Publisher(_))
+        ));
+        assert_eq!(repository.pending(10).await.unwrap().len(), 1);
+
+        relay.publisher().remaining.store(1, Ordering::SeqCst);
+        assert_eq!(relay.run_until_empty().await.unwrap(), 1);
+        assert!(repository.pending(10).await.unwrap().is_empty());
+        assert_eq!(
+            relay
+                .publisher()
+                .inner
+                .published()
+                .into_iter()
+                .map(|envelope| envelope.event.id())
+                .collect::<Vec<_>>(),
+            vec![AggregateId("1".to_owned()), AggregateId("2".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_outbox_disabled() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let publisher = InMemoryPublisher::new();
+        let relay = OutboxRelay::new(repository.clone(), publisher.clone(), 10);
+
+        create(&repository, "1").await;
+        assert!(matches!(repository.pending(10).await, Err(OutboxDisabled)));
+        assert!(matches!(
+            repository.mark_dispatched(&[0]).await,
+            Err(OutboxDisabled)
+        ));
+        assert!(matches!(
+            relay.run_until_empty().await,
+            Err(OutboxError::Outbox(OutboxDisabled))
+        ));
+        assert!(publisher.published().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_relay_empty_batch() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new().with_outbox();
+        let publisher = InMemoryPublisher::new();
+        let relay = OutboxRelay::new(repository.clone(), publisher.clone(), 0);
+
+        create(&repository, "1").await;
+        create(&repository, "2").await;
+        assert_eq!(relay.run_once().await.unwrap(), 1);
+        assert_eq!(relay.run_until_empty().await.unwrap(), 1);
+        assert_eq!(publisher.published().len(), 2);
+    }
+}
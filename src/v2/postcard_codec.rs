@@ -0,0 +1,89 @@
+use super::event_codec::check_schema;
+use super::{EncodedEvent, EventCodec, EventSchema};
+
+pub struct PostcardCodec<E> {
+    _event: std::marker::PhantomData<fn() -> E>,
+}
+
+impl<E> PostcardCodec<E> {
+    pub fn new() -> Self {
+        Self {
+            _event: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<E> Default for PostcardCodec<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> EventCodec for PostcardCodec<E>
+where
+    E: EventSchema + serde::Serialize + serde::de::DeserializeOwned,
+{
+    type Event = E;
+    type Error = postcard::Error;
+
+    fn encode(&self, event: &Self::Event) -> Result<EncodedEvent, Self::Error> {
+        Ok(EncodedEvent {
+            event_type: event.event_type().to_owned(),
+            schema_version: event.schema_version(),
+            payload: postcard::to_allocvec(event)?,
+        })
+    }
+
+    fn decode(&self, encoded: &EncodedEvent) -> Result<Self::Event, Self::Error> {
+        check_schema(postcard::from_bytes(&encoded.payload)?, encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+    enum OrderEvent {
+        Placed { id: String, version: u16 },
+    }
+
+    impl EventSchema for OrderEvent {
+        fn event_type(&self) -> &'static str {
+            "OrderPlaced"
+        }
+
+        fn schema_version(&self) -> u32 {
+            2
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let codec = PostcardCodec::<OrderEvent>::new();
+        let event = OrderEvent::Placed {
+            id: "1".to_owned(),
+            version: 1,
+        };
+
+        let encoded = codec.encode(&event).unwrap();
+        assert_eq!(encoded.event_type, "OrderPlaced");
+        assert_eq!(encoded.schema_version, 2);
+        assert_eq!(encoded.payload, vec![0, 1, b'1', 1]);
+        assert_eq!(codec.decode(&encoded).unwrap(), event);
+    }
+
+    #[test]
+    fn test_decode_undeclared_schema_version() {
+        let codec = PostcardCodec::<OrderEvent>::new();
+        assert!(
+            codec
+                .decode(&EncodedEvent {
+                    event_type: "OrderPlaced".to_owned(),
+                    schema_version: 1,
+                    payload: vec![0, 1, b'1', 1],
+                })
+                .is_err()
+        );
+    }
+}
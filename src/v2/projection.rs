@@ -0,0 +1,291 @@
+use super::{Aggregate, EventLog, Position, RecordedEvent};
+
+#[async_trait::async_trait]
+pub trait Projection {
+    type Event;
+    type Error: std::error::Error;
+
+    async fn apply(&mut self, event: &RecordedEvent<Self::Event>) -> Result<(), Self::Error>;
+
+    async fn reset(&mut self) -> Result<(), Self::Error>;
+}
+
+#[async_trait::async_trait]
+pub trait CheckpointStore {
+    type Error: std::error::Error;
+
+    async fn load(&self, name: &str) -> Result<Option<Position>, Self::Error>;
+
+    async fn save(&self, name: &str, position: Position) -> Result<(), Self::Error>;
+
+    async fn clear(&self, name: &str) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug)]
+pub enum ProjectionError<L, P, C> {
+    EventLog(L),
+    Projection(P),
+    Checkpoint(C),
+}
+
+impl<L, P, C> std::fmt::Display for ProjectionError<L, P, C>
+where
+    L: std::fmt::Display,
+    P: std::fmt::Display,
+    C: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EventLog(e) => std::fmt::Display::fmt(e, f),
+            Self::Projection(e) => std::fmt::Display::fmt(e, f),
+            Self::Checkpoint(e) => std::fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<L, P, C> std::error::Error for ProjectionError<L, P, C>
+where
+    L: std::error::Error + 'static,
+    P: std::error::Error + 'static,
+    C: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::EventLog(e) => Some(e),
+            Self::Projection(e) => Some(e),
+            Self::Checkpoint(e) => Some(e),
+        }
+    }
+}
+
+pub struct ProjectionRunner<L, P, C> {
+    name: String,
+    event_log: L,
+    projection: P,
+    checkpoint_store: C,
+    batch_size: usize,
+}
+
+impl<L, P, C> ProjectionRunner<L, P, C>
+where
+    L: EventLog,
+    P: Projection<Event = <L::Aggregate as Aggregate>::Event>,
+    C: CheckpointStore,
+{
+    pub fn new(
+        name: impl Into<String>,
+        event_log: L,
+        projection: P,
+        checkpoint_store: C,
+        batch_size: usize,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            event_log,
+            projection,
+            checkpoint_store,
+            // an empty batch would look caught up without reading anything
+            batch_size: batch_size.max(1),
+        }
+    }
+
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    pub async fn run_once(
+        &mut self,
+    ) -> Result<usize, ProjectionError<L::Error, P::Error, C::Error>> {
+        let checkpoint = self
+            .checkpoint_store
+            .load(&self.name)
+            .await
+            .map_err(ProjectionError::Checkpoint)?;
+        let from_position = checkpoint.map(|it| it.next()).unwrap_or_default();
+        let events = self
+            .event_log
+            .read_all(from_position, self.batch_size)
+            .await
+            .map_err(ProjectionError::EventLog)?;
+        for event in &events {
+            self.projection
+                .apply(event)
+                .await
+                .map_err(ProjectionError::Projection)?;
+        }
+        if let Some(last) = events.last() {
+            self.checkpoint_store
+                .save(&self.name, last.position)
+                .await
+                .map_err(ProjectionError::Checkpoint)?;
+        }
+        Ok(events.len())
+    }
+
+    pub async fn run_until_caught_up(
+        &mut self,
+    ) -> Result<usize, ProjectionError<L::Error, P::Error, C::Error>> {
+        let mut total = 0;
+        loop {
+            match self.run_once().await? {
+                0 => return Ok(total),
+                n => total += n,
+            }
+        }
+    }
+
+    pub async fn rebuild(
+        &mut self,
+    ) -> Result<usize, ProjectionError<L::Error, P::Error, C::Error>> {
+        self.projection
+            .reset()
+            .await
+            .map_err(ProjectionError::Projection)?;
+        self.checkpoint_store
+            .clear(&self.name)
+            .await
+            .map_err(ProjectionError::Checkpoint)?;
+        self.run_until_caught_up().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::v2::tests::{AggregateEvent, AggregateId, AggregateImpl, AggregateVersion, create};
+    use crate::v2::{Event, InMemoryCheckpointStore, InMemoryEventStore, Repository};
+
+    #[derive(Default)]
+    struct VersionProjection {
+        versions: BTreeMap<AggregateId, AggregateVersion>,
+        applied: usize,
+    }
+
+    #[async_trait::async_trait]
+    impl Projection for VersionProjection {
+        type Event = AggregateEvent;
+        type Error = std::io::Error;
+
+        async fn apply(&mut self, event: &RecordedEvent<Self::Event>) -> Result<(), Self::Error> {
+            self.versions
+                .insert(event.event.id(), event.event.version());
+            self.applied += 1;
+            Ok(())
+        }
+
+        async fn reset(&mut self) -> Result<(), Self::Error> {
+            self.versions.clear();
+            self.applied = 0;
+            Ok(())
+        }
+    }
+
+    async fn update(repository: &InMemoryEventStore<AggregateImpl>, id: &str) {
+        let id = AggregateId(id.to_owned());
+        let aggregate = repository.find(&id).await.unwrap().unwrap();
+        let (_, events) = aggregate.update().unwrap();
+        repository
+            .store(&id, Some(&aggregate.version()), &events)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_resumes_from_checkpoint() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let checkpoint_store = InMemoryCheckpointStore::new();
+        create(&repository, "1").await;
+        create(&repository, "2").await;
+        update(&repository, "1").await;
+
+        let mut runner = ProjectionRunner::new(
+            "versions",
+            repository.clone(),
+            VersionProjection::default(),
+            checkpoint_store.clone(),
+            2,
+        );
+        assert_eq!(runner.run_once().await.unwrap(), 2);
+        assert_eq!(
+            checkpoint_store.load("versions").await.unwrap(),
+            Some(Position(1))
+        );
+        assert_eq!(runner.run_until_caught_up().await.unwrap(), 1);
+        assert_eq!(
+            runner
+                .projection()
+                .versions
+                .get(&AggregateId("1".to_owned())),
+            Some(&AggregateVersion(2))
+        );
+
+        // restart
+        update(&repository, "2").await;
+        let mut runner = ProjectionRunner::new(
+            "versions",
+            repository.clone(),
+            VersionProjection::default(),
+            checkpoint_store.clone(),
+            2,
+        );
+        assert_eq!(runner.run_until_caught_up().await.unwrap(), 1);
+        assert_eq!(runner.projection().applied, 1);
+        assert_eq!(
+            runner
+                .projection()
+                .versions
+                .get(&AggregateId("2".to_owned())),
+            Some(&AggregateVersion(2))
+        );
+        assert_eq!(
+            checkpoint_store.load("versions").await.unwrap(),
+            Some(Position(3))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zero_batch_size() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        create(&repository, "1").await;
+        update(&repository, "1").await;
+
+        let mut runner = ProjectionRunner::new(
+            "versions",
+            repository.clone(),
+            VersionProjection::default(),
+            InMemoryCheckpointStore::new(),
+            0,
+        );
+        assert_eq!(runner.run_until_caught_up().await.unwrap(), 2);
+        assert_eq!(runner.projection().applied, 2);
+    }
+
+    #[tokio::test]
+    async fn test_rebuild() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        create(&repository, "1").await;
+        update(&repository, "1").await;
+
+        let mut runner = ProjectionRunner::new(
+            "versions",
+            repository.clone(),
+            VersionProjection::default(),
+            InMemoryCheckpointStore::new(),
+            10,
+        );
+        assert_eq!(runner.run_until_caught_up().await.unwrap(), 2);
+        assert_eq!(runner.run_until_caught_up().await.unwrap(), 0);
+
+        assert_eq!(runner.rebuild().await.unwrap(), 2);
+        assert_eq!(runner.projection().applied, 2);
+        assert_eq!(
+            runner
+                .projection()
+                .versions
+                .get(&AggregateId("1".to_owned())),
+            Some(&AggregateVersion(2))
+        );
+    }
+}
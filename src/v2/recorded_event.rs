@@ -0,0 +1,21 @@
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Position(pub u64);
+
+impl Position {
+    pub fn next(&self) -> Self {
+        Self(self.0 + 1)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordedEvent<E> {
+    pub position: Position,
+    pub event: E,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordedDeletion<I> {
+    pub position: Position,
+    pub id: I,
+    pub purged: bool,
+}
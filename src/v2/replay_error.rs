@@ -0,0 +1,34 @@
+#[derive(Debug)]
+pub enum ReplayError {
+    NoEvents,
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoEvents => write!(f, "No events provided"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+// lets aggregates use `std::io::Error` as their error type, which the orphan rule would
+// otherwise prevent downstream crates from doing
+impl From<ReplayError> for std::io::Error {
+    fn from(e: ReplayError) -> Self {
+        std::io::Error::other(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_io_error() {
+        let e = std::io::Error::from(ReplayError::NoEvents);
+        assert_eq!(e.kind(), std::io::ErrorKind::Other);
+        assert_eq!(e.to_string(), "No events provided");
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::{Debug, Display};
+
+#[derive(Debug)]
+pub enum RepositoryError<I, V, E> {
+    AlreadyExists { id: I },
+    VersionConflict { expected: V, actual: V },
+    NotFound { id: I },
+    Deleted { id: I },
+    Backend(E),
+}
+
+impl<I, V, E> RepositoryError<I, V, E> {
+    pub fn is_conflict(&self) -> bool {
+        matches!(
+            self,
+            Self::AlreadyExists { .. } | Self::VersionConflict { .. }
+        )
+    }
+}
+
+impl<I, V, E> Display for RepositoryError<I, V, E>
+where
+    I: Debug,
+    V: Debug,
+    E: Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyExists { id } => write!(f, "Aggregate already exists (id = {:?})", id),
+            Self::VersionConflict { expected, actual } => write!(
+                f,
+                "Version mismatch (expected = {:?}, actual = {:?})",
+                expected, actual
+            ),
+            Self::NotFound { id } => write!(f, "Aggregate not found (id = {:?})", id),
+            Self::Deleted { id } => write!(f, "Aggregate deleted (id = {:?})", id),
+            Self::Backend(e) => Display::fmt(e, f),
+        }
+    }
+}
+
+impl<I, V, E> std::error::Error for RepositoryError<I, V, E>
+where
+    I: Debug,
+    V: Debug,
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Backend(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Error = RepositoryError<String, u16, std::io::Error>;
+
+    #[test]
+    fn test_is_conflict() {
+        assert!(Error::AlreadyExists { id: "1".to_owned() }.is_conflict());
+        assert!(
+            Error::VersionConflict {
+                expected: 1,
+                actual: 2
+            }
+            .is_conflict()
+        );
+        assert!(!Error::NotFound { id: "1".to_owned() }.is_conflict());
+        assert!(!Error::Deleted { id: "1".to_owned() }.is_conflict());
+        assert!(!Error::Backend(std::io::Error::other("backend")).is_conflict());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Error::VersionConflict {
+                expected: 1,
+                actual: 2
+            }
+            .to_string(),
+            "Version mismatch (expected = 1, actual = 2)"
+        );
+        assert_eq!(
+            Error::Backend(std::io::Error::other("backend")).to_string(),
+            "backend"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use super::{Aggregate, CommandError, Repository, execute};
+
+type Sleep = Box<dyn Fn(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+type Random = Box<dyn Fn() -> u64 + Send + Sync>;
+
+// Delays are slept with `tokio::time::sleep` when the `tokio` feature is enabled and skipped
+// otherwise, unless another sleep function is given. Jitter needs a source of random numbers.
+pub struct RetryPolicy<F> {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: Option<Random>,
+    sleep: Sleep,
+    retryable: F,
+}
+
+impl<F> RetryPolicy<F> {
+    pub fn new(max_attempts: u32, retryable: F) -> Self {
+        Self {
+            max_attempts,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            jitter: None,
+            #[cfg(feature = "tokio")]
+            sleep: Box::new(|delay| Box::pin(tokio::time::sleep(delay))),
+            #[cfg(not(feature = "tokio"))]
+            sleep: Box::new(|_| Box::pin(std::future::ready(()))),
+            retryable,
+        }
+    }
+
+    pub fn with_backoff(self, base_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            base_delay,
+            max_delay,
+            ..self
+        }
+    }
+
+    // each delay is drawn between half of it and all of it using `random`
+    pub fn with_jitter<J>(self, random: J) -> Self
+    where
+        J: Fn() -> u64 + Send + Sync + 'static,
+    {
+        Self {
+            jitter: Some(Box::new(random)),
+            ..self
+        }
+    }
+
+    pub fn with_sleep<S, T>(self, sleep: S) -> Self
+    where
+        S: Fn(Duration) -> T + Send + Sync + 'static,
+        T: Future<Output = ()> + Send + 'static,
+    {
+        Self {
+            sleep: Box::new(move |delay| Box::pin(sleep(delay))),
+            ..self
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn is_retryable<E>(&self, error: &E) -> bool
+    where
+        F: Fn(&E) -> bool,
+    {
+        (self.retryable)(error)
+    }
+
+    // delay before the retry following the given (1-based) attempt
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        let Some(random) = &self.jitter else {
+            return delay;
+        };
+        let half = delay / 2;
+        half + Duration::from_nanos(random() % (half.as_nanos() as u64 + 1))
+    }
+
+    async fn sleep(&self, attempt: u32) {
+        (self.sleep)(self.delay(attempt)).await
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub async fn execute_with_retry<R, F, D, P>(
+    repository: &R,
+    id: &<R::Aggregate as Aggregate>::Id,
+    policy: &RetryPolicy<P>,
+    mut decide: F,
+) -> Result<R::Aggregate, CommandError<R::Error, <R::Aggregate as Aggregate>::Error, D>>
+where
+    R: Repository,
+    F: FnMut(Option<&R::Aggregate>) -> Result<Vec<<R::Aggregate as Aggregate>::Event>, D>,
+    P: Fn(&R::Error) -> bool,
+{
+    let mut attempt = 1;
+    loop {
+        match execute(repository, id, &mut decide).await {
+            Err(CommandError::Repository(e))
+                if attempt < policy.max_attempts() && policy.is_retryable(&e) =>
+            {
+                policy.sleep(attempt).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::tests::{AggregateEvent, AggregateId, AggregateImpl, AggregateVersion, create};
+    use crate::v2::{InMemoryEventStore, RepositoryError};
+
+    type Error = RepositoryError<AggregateId, AggregateVersion, std::io::Error>;
+
+    fn on_conflict(e: &Error) -> bool {
+        e.is_conflict()
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new(5, on_conflict)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(50));
+        assert_eq!(policy.delay(1), Duration::from_millis(10));
+        assert_eq!(policy.delay(2), Duration::from_millis(20));
+        assert_eq!(policy.delay(3), Duration::from_millis(40));
+        assert_eq!(policy.delay(4), Duration::from_millis(50));
+        assert_eq!(policy.delay(100), Duration::from_millis(50));
+
+        let policy = policy.with_jitter(|| 0);
+        assert_eq!(policy.delay(1), Duration::from_millis(5));
+        assert_eq!(policy.delay(4), Duration::from_millis(25));
+        let policy = policy.with_jitter(|| 5_000_000);
+        assert_eq!(policy.delay(1), Duration::from_millis(10));
+        assert_eq!(policy.delay(2), Duration::from_millis(15));
+    }
+
+    #[test]
+    fn test_execute_with_retry_with_sleep() {
+        use futures::FutureExt as _;
+
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        futures::executor::block_on(create(&repository, &id.0));
+
+        let slept = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let policy = RetryPolicy::new(3, on_conflict)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(50))
+            .with_sleep({
+                let slept = slept.clone();
+                move |delay| {
+                    slept.lock().unwrap().push(delay);
+                    std::future::ready(())
+                }
+            });
+        // runs without a Tokio runtime
+        let result = futures::executor::block_on(execute_with_retry(
+            &repository,
+            &id,
+            &policy,
+            |aggregate| {
+                let aggregate = aggregate.unwrap();
+                let (_, events) = aggregate.update()?;
+                repository
+                    .store(&id, Some(&aggregate.version()), &events)
+                    .now_or_never()
+                    .unwrap()
+                    .unwrap();
+                Ok::<_, std::io::Error>(events)
+            },
+        ));
+        assert!(result.is_err());
+        assert_eq!(
+            *slept.lock().unwrap(),
+            vec![Duration::from_millis(10), Duration::from_millis(20)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_retry() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        create(&repository, &id.0).await;
+
+        let policy = RetryPolicy::new(3, on_conflict).with_backoff(Duration::ZERO, Duration::ZERO);
+        let mut attempts = 0;
+        let aggregate = execute_with_retry(&repository, &id, &policy, |aggregate| {
+            attempts += 1;
+            let (_, events) = aggregate.unwrap().update()?;
+            if attempts == 1 {
+                // a concurrent writer stores first
+                futures::executor::block_on(repository.store(
+                    &id,
+                    Some(&AggregateVersion(1)),
+                    &events,
+                ))
+                .unwrap();
+            }
+            Ok::<_, std::io::Error>(events)
+        })
+        .await
+        .unwrap();
+        assert_eq!(attempts, 2);
+        assert_eq!(aggregate.version(), AggregateVersion(3));
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_retry_gives_up() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+        create(&repository, &id.0).await;
+
+        let policy = RetryPolicy::new(3, on_conflict).with_backoff(Duration::ZERO, Duration::ZERO);
+        let mut attempts = 0;
+        let result = execute_with_retry(&repository, &id, &policy, |aggregate| {
+            attempts += 1;
+            let aggregate = aggregate.unwrap();
+            let (_, events) = aggregate.update()?;
+            futures::executor::block_on(repository.store(&id, Some(&aggregate.version()), &events))
+                .unwrap();
+            Ok::<_, std::io::Error>(events)
+        })
+        .await;
+        assert_eq!(attempts, 3);
+        assert!(matches!(
+            result,
+            Err(CommandError::Repository(
+                RepositoryError::VersionConflict { .. }
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_retry_not_retryable() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let id = AggregateId("1".to_owned());
+
+        let policy = RetryPolicy::new(3, on_conflict).with_backoff(Duration::ZERO, Duration::ZERO);
+        let mut attempts = 0;
+        let result = execute_with_retry(&repository, &id, &policy, |_| {
+            attempts += 1;
+            Err::<Vec<AggregateEvent>, _>(std::io::Error::other("Aggregate not found"))
+        })
+        .await;
+        assert_eq!(attempts, 1);
+        assert!(matches!(result, Err(CommandError::Rejected(_))));
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use super::{Aggregate, DeletableRepository, EventReader, Repository};
+
+pub trait Snapshot: Aggregate {
+    type State;
+
+    fn snapshot(&self) -> Self::State;
+
+    fn restore(state: Self::State) -> Result<Self, Self::Error>;
+
+    fn replay_from<I>(state: Self::State, events: I) -> Result<Self, Self::Error>
+    where
+        I: IntoIterator<Item = Self::Event>,
+    {
+        events
+            .into_iter()
+            .try_fold(Self::restore(state)?, Self::apply)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait SnapshotStore {
+    type Aggregate: Snapshot;
+    type Error: std::error::Error;
+
+    #[allow(clippy::type_complexity)]
+    async fn load(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<
+        Option<(
+            <Self::Aggregate as Aggregate>::Version,
+            <Self::Aggregate as Snapshot>::State,
+        )>,
+        Self::Error,
+    >;
+
+    async fn save(&self, aggregate: &Self::Aggregate) -> Result<(), Self::Error>;
+
+    async fn remove(&self, id: &<Self::Aggregate as Aggregate>::Id) -> Result<(), Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SnapshotPolicy {
+    Never,
+    EveryNEvents(usize),
+}
+
+impl SnapshotPolicy {
+    pub fn should_snapshot(&self, events_since_snapshot: usize) -> bool {
+        match self {
+            Self::Never => false,
+            Self::EveryNEvents(n) => *n > 0 && events_since_snapshot >= *n,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotRepositoryError<R, S, A> {
+    Repository(R),
+    Snapshot(S),
+    Aggregate(A),
+}
+
+impl<R, S, A> std::fmt::Display for SnapshotRepositoryError<R, S, A>
+where
+    R: std::fmt::Display,
+    S: std::fmt::Display,
+    A: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Repository(e) => std::fmt::Display::fmt(e, f),
+            Self::Snapshot(e) => std::fmt::Display::fmt(e, f),
+            Self::Aggregate(e) => std::fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<R, S, A> std::error::Error for SnapshotRepositoryError<R, S, A>
+where
+    R: std::error::Error + 'static,
+    S: std::error::Error + 'static,
+    A: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Repository(e) => Some(e),
+            Self::Snapshot(e) => Some(e),
+            Self::Aggregate(e) => Some(e),
+        }
+    }
+}
+
+pub struct SnapshotRepository<R: Repository, S> {
+    repository: R,
+    snapshot_store: S,
+    policy: SnapshotPolicy,
+    // events stored since the last snapshot, as of the last load or store of each aggregate
+    events_since_snapshot: Mutex<HashMap<<R::Aggregate as Aggregate>::Id, usize>>,
+}
+
+impl<R, S> SnapshotRepository<R, S>
+where
+    R: EventReader,
+    R::Aggregate: Snapshot,
+    <R::Aggregate as Aggregate>::Id: Sync,
+    S: SnapshotStore<Aggregate = R::Aggregate>,
+{
+    pub fn new(repository: R, snapshot_store: S, policy: SnapshotPolicy) -> Self {
+        Self {
+            repository,
+            snapshot_store,
+            policy,
+            events_since_snapshot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn repository(&self) -> &R {
+        &self.repository
+    }
+
+    pub fn snapshot_store(&self) -> &S {
+        &self.snapshot_store
+    }
+
+    #[allow(clippy::type_complexity)]
+    async fn load(
+        &self,
+        id: &<R::Aggregate as Aggregate>::Id,
+    ) -> Result<
+        Option<(R::Aggregate, usize)>,
+        SnapshotRepositoryError<R::Error, S::Error, <R::Aggregate as Aggregate>::Error>,
+    > {
+        if self
+            .repository
+            .is_deleted(id)
+            .await
+            .map_err(SnapshotRepositoryError::Repository)?
+        {
+            return Ok(None);
+        }
+        let snapshot = self
+            .snapshot_store
+            .load(id)
+            .await
+            .map_err(SnapshotRepositoryError::Snapshot)?;
+        let (version, state) = match snapshot {
+            None => (None, None),
+            Some((version, state)) => (Some(version), Some(state)),
+        };
+        let events = self
+            .repository
+            .read_events(id, version.as_ref())
+            .await
+            .map_err(SnapshotRepositoryError::Repository)?;
+        let events_since_snapshot = events.len();
+        let aggregate = match state {
+            None if events.is_empty() => return Ok(None),
+            None => R::Aggregate::replay(events),
+            Some(state) => R::Aggregate::replay_from(state, events),
+        }
+        .map_err(SnapshotRepositoryError::Aggregate)?;
+        Ok(Some((aggregate, events_since_snapshot)))
+    }
+
+    #[allow(clippy::type_complexity)]
+    async fn remove_snapshot(
+        &self,
+        id: &<R::Aggregate as Aggregate>::Id,
+    ) -> Result<(), SnapshotRepositoryError<R::Error, S::Error, <R::Aggregate as Aggregate>::Error>>
+    where
+        <R::Aggregate as Aggregate>::Id: Hash,
+    {
+        self.events_since_snapshot.lock().unwrap().remove(id);
+        self.snapshot_store
+            .remove(id)
+            .await
+            .map_err(SnapshotRepositoryError::Snapshot)
+    }
+}
+
+#[async_trait::async_trait]
+impl<R, S> Repository for SnapshotRepository<R, S>
+where
+    R: EventReader + Send + Sync,
+    R::Aggregate: Snapshot + Send + Sync,
+    <R::Aggregate as Aggregate>::Error: std::error::Error + Send + 'static,
+    <R::Aggregate as Aggregate>::Event: Send + Sync,
+    <R::Aggregate as Aggregate>::Id: Clone + Hash + Send + Sync,
+    <R::Aggregate as Aggregate>::Version: Send + Sync,
+    <R::Aggregate as Snapshot>::State: Send,
+    R::Error: Send + 'static,
+    S: SnapshotStore<Aggregate = R::Aggregate> + Send + Sync,
+    S::Error: Send + 'static,
+{
+    type Aggregate = R::Aggregate;
+    type Error = SnapshotRepositoryError<R::Error, S::Error, <R::Aggregate as Aggregate>::Error>;
+
+    async fn find(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<Option<Self::Aggregate>, Self::Error> {
+        let Some((aggregate, events_since_snapshot)) = self.load(id).await? else {
+            return Ok(None);
+        };
+        self.events_since_snapshot
+            .lock()
+            .unwrap()
+            .insert(id.clone(), events_since_snapshot);
+        Ok(Some(aggregate))
+    }
+
+    async fn store(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[<Self::Aggregate as Aggregate>::Event],
+    ) -> Result<(), Self::Error> {
+        self.repository
+            .store(id, expected_version, new_events)
+            .await
+            .map_err(SnapshotRepositoryError::Repository)?;
+
+        if self.policy == SnapshotPolicy::Never || new_events.is_empty() {
+            return Ok(());
+        }
+        let events_since_snapshot = {
+            let mut counts = self.events_since_snapshot.lock().unwrap();
+            let count = counts.entry(id.clone()).or_default();
+            *count += new_events.len();
+            *count
+        };
+        // the events are stored at this point, so a failed snapshot does not fail the store
+        // and is attempted again by the next one
+        if self.policy.should_snapshot(events_since_snapshot)
+            && let Ok(Some((aggregate, _))) = self.load(id).await
+            && self.snapshot_store.save(&aggregate).await.is_ok()
+        {
+            self.events_since_snapshot.lock().unwrap().remove(id);
+        }
+        Ok(())
+    }
+}
+
+// The snapshot is removed before the stream: it can be rebuilt from the events if deleting the
+// stream fails, while a purge could not be retried to remove a snapshot left behind.
+#[async_trait::async_trait]
+impl<R, S> DeletableRepository for SnapshotRepository<R, S>
+where
+    R: DeletableRepository + EventReader + Send + Sync,
+    R::Aggregate: Snapshot + Send + Sync,
+    <R::Aggregate as Aggregate>::Error: std::error::Error + Send + 'static,
+    <R::Aggregate as Aggregate>::Event: Send + Sync,
+    <R::Aggregate as Aggregate>::Id: Clone + Hash + Send + Sync,
+    <R::Aggregate as Aggregate>::Version: Send + Sync,
+    <R::Aggregate as Snapshot>::State: Send,
+    R::Error: Send + 'static,
+    S: SnapshotStore<Aggregate = R::Aggregate> + Send + Sync,
+    S::Error: Send + 'static,
+{
+    async fn delete(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        self.remove_snapshot(id).await?;
+        self.repository
+            .delete(id, expected_version)
+            .await
+            .map_err(SnapshotRepositoryError::Repository)
+    }
+
+    async fn purge(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        self.remove_snapshot(id).await?;
+        self.repository
+            .purge(id, expected_version)
+            .await
+            .map_err(SnapshotRepositoryError::Repository)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::testing::repository_conformance;
+    use crate::v2::tests::{
+        AggregateCreated, AggregateEvent, AggregateId, AggregateImpl, AggregateVersion, Fixture,
+        create,
+    };
+    use crate::v2::{InMemoryEventStore, InMemorySnapshotStore};
+
+    impl Snapshot for AggregateImpl {
+        type State = (AggregateId, AggregateVersion);
+
+        fn snapshot(&self) -> Self::State {
+            (self.id.clone(), self.version.clone())
+        }
+
+        fn restore(state: Self::State) -> Result<Self, Self::Error> {
+            let (id, version) = state;
+            Ok(Self { id, version })
+        }
+    }
+
+    #[test]
+    fn test_snapshot_policy() {
+        assert!(!SnapshotPolicy::Never.should_snapshot(100));
+        assert!(!SnapshotPolicy::EveryNEvents(0).should_snapshot(100));
+        assert!(!SnapshotPolicy::EveryNEvents(3).should_snapshot(2));
+        assert!(SnapshotPolicy::EveryNEvents(3).should_snapshot(3));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_repository() {
+        let event_store = InMemoryEventStore::<AggregateImpl>::new();
+        let snapshot_store = InMemorySnapshotStore::<AggregateImpl>::new();
+        let repository = SnapshotRepository::new(
+            event_store.clone(),
+            snapshot_store.clone(),
+            SnapshotPolicy::EveryNEvents(2),
+        );
+        let id = AggregateId("1".to_owned());
+
+        assert!(repository.find(&id).await.unwrap().is_none());
+
+        repository
+            .store(
+                &id,
+                None,
+                &[AggregateEvent::Created(AggregateCreated {
+                    id: id.0.clone(),
+                    version: 1,
+                })],
+            )
+            .await
+            .unwrap();
+        assert!(snapshot_store.load(&id).await.unwrap().is_none());
+
+        for _ in 0..2 {
+            let aggregate = repository.find(&id).await.unwrap().unwrap();
+            let (_, events) = aggregate.update().unwrap();
+            repository
+                .store(&id, Some(&aggregate.version()), &events)
+                .await
+                .unwrap();
+        }
+
+        let (version, _) = snapshot_store.load(&id).await.unwrap().unwrap();
+        assert_eq!(version, AggregateVersion(2));
+
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), AggregateVersion(3));
+        assert_eq!(
+            found_aggregate.version(),
+            event_store.find(&id).await.unwrap().unwrap().version()
+        );
+    }
+
+    struct FailingSnapshotStore;
+
+    #[async_trait::async_trait]
+    impl SnapshotStore for FailingSnapshotStore {
+        type Aggregate = AggregateImpl;
+        type Error = std::io::Error;
+
+        async fn load(
+            &self,
+            _: &AggregateId,
+        ) -> Result<Option<(AggregateVersion, (AggregateId, AggregateVersion))>, Self::Error>
+        {
+            Ok(None)
+        }
+
+        async fn save(&self, _: &Self::Aggregate) -> Result<(), Self::Error> {
+            Err(std::io::Error::other("Snapshot store unavailable"))
+        }
+
+        async fn remove(&self, _: &AggregateId) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_snapshot_does_not_fail_store() {
+        let event_store = InMemoryEventStore::<AggregateImpl>::new();
+        let repository = SnapshotRepository::new(
+            event_store.clone(),
+            FailingSnapshotStore,
+            SnapshotPolicy::EveryNEvents(1),
+        );
+        let id = AggregateId("1".to_owned());
+
+        repository
+            .store(
+                &id,
+                None,
+                &[AggregateEvent::Created(AggregateCreated {
+                    id: id.0.clone(),
+                    version: 1,
+                })],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            event_store.find(&id).await.unwrap().unwrap().version(),
+            AggregateVersion(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_purge() {
+        let event_store = InMemoryEventStore::<AggregateImpl>::new();
+        let snapshot_store = InMemorySnapshotStore::<AggregateImpl>::new();
+        let repository = SnapshotRepository::new(
+            event_store.clone(),
+            snapshot_store.clone(),
+            SnapshotPolicy::EveryNEvents(1),
+        );
+        let id1 = AggregateId("1".to_owned());
+        let id2 = AggregateId("2".to_owned());
+        create(&repository, &id1.0).await;
+        create(&repository, &id2.0).await;
+        assert!(snapshot_store.load(&id1).await.unwrap().is_some());
+
+        repository.delete(&id1, &AggregateVersion(1)).await.unwrap();
+        assert!(repository.find(&id1).await.unwrap().is_none());
+        assert!(snapshot_store.load(&id1).await.unwrap().is_none());
+
+        // deleted in the event store directly, leaving the snapshot behind
+        event_store
+            .delete(&id2, &AggregateVersion(1))
+            .await
+            .unwrap();
+        assert!(snapshot_store.load(&id2).await.unwrap().is_some());
+        assert!(repository.find(&id2).await.unwrap().is_none());
+
+        repository.purge(&id2, &AggregateVersion(1)).await.unwrap();
+        assert!(snapshot_store.load(&id2).await.unwrap().is_none());
+        assert!(
+            event_store
+                .read_events(&id2, None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        repository_conformance(&Fixture(|| {
+            SnapshotRepository::new(
+                InMemoryEventStore::<AggregateImpl>::new(),
+                InMemorySnapshotStore::<AggregateImpl>::new(),
+                SnapshotPolicy::EveryNEvents(1),
+            )
+        }))
+        .await;
+    }
+}
@@ -0,0 +1,883 @@
+use std::fmt::{Debug, Display};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use rusqlite::OptionalExtension;
+
+use super::event_envelope::{decode_metadata, encode_metadata, recorded_at};
+use super::{
+    Aggregate, DeletableRepository, EncodedEvent, EnvelopeRepository, Event, EventCodec,
+    EventEnvelope, EventReader, Outbox, OutboxDisabled, OutboxEntry, Repository, RepositoryError,
+};
+
+// `version` is the 1-based position of the event in its stream. The primary key makes
+// two writers appending at the same position fail atomically. `outbox` holds the events
+// not yet dispatched by an outbox relay, if enabled by `with_outbox`, and is written in the same
+// transaction. `tombstones` holds the deleted streams.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    aggregate_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    event_type TEXT NOT NULL,
+    schema_version INTEGER NOT NULL,
+    payload BLOB NOT NULL,
+    metadata BLOB NOT NULL,
+    PRIMARY KEY (aggregate_id, version)
+);
+CREATE TABLE IF NOT EXISTS outbox (
+    sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+    aggregate_id TEXT NOT NULL,
+    version INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tombstones (
+    aggregate_id TEXT PRIMARY KEY
+)";
+
+pub struct SqliteEventStore<A, C> {
+    connection: Mutex<rusqlite::Connection>,
+    codec: C,
+    outbox: bool,
+    _aggregate: std::marker::PhantomData<fn() -> A>,
+}
+
+impl<A, C> SqliteEventStore<A, C>
+where
+    A: Aggregate,
+    A::Id: Display,
+    C: EventCodec<Event = A::Event>,
+{
+    pub fn open<P: AsRef<Path>>(path: P, codec: C) -> rusqlite::Result<Self> {
+        Self::new(rusqlite::Connection::open(path)?, codec)
+    }
+
+    pub fn open_in_memory(codec: C) -> rusqlite::Result<Self> {
+        Self::new(rusqlite::Connection::open_in_memory()?, codec)
+    }
+
+    fn new(connection: rusqlite::Connection, codec: C) -> rusqlite::Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            codec,
+            outbox: false,
+            _aggregate: std::marker::PhantomData,
+        })
+    }
+
+    pub fn with_outbox(mut self) -> Self {
+        self.outbox = true;
+        self
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn read_log(
+        &self,
+        connection: &rusqlite::Connection,
+        id: &A::Id,
+    ) -> Result<Vec<EventEnvelope<A::Event>>, SqliteEventStoreError<C::Error, A::Error>> {
+        let mut statement = connection.prepare_cached(
+            "SELECT version, event_type, schema_version, payload, metadata FROM events WHERE aggregate_id = ?1 ORDER BY version ASC",
+        )?;
+        let rows = statement.query_map([id.to_string()], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                encoded_event(row, 1)?,
+                row.get::<_, Vec<u8>>(4)?,
+            ))
+        })?;
+        let mut envelopes = vec![];
+        for row in rows {
+            let (position, encoded, metadata) = row?;
+            // events split by an upcaster share the metadata of their row
+            for event in self
+                .codec
+                .decode_all(&encoded)
+                .map_err(SqliteEventStoreError::Codec)?
+            {
+                envelopes.push(
+                    decode_metadata(&metadata, event)
+                        .ok_or(SqliteEventStoreError::InvalidMetadata { position })?,
+                );
+            }
+        }
+        Ok(envelopes)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn last_event(
+        &self,
+        connection: &rusqlite::Connection,
+        id: &A::Id,
+    ) -> Result<Option<(i64, A::Event)>, SqliteEventStoreError<C::Error, A::Error>> {
+        let mut statement = connection.prepare_cached(
+            "SELECT version, event_type, schema_version, payload FROM events WHERE aggregate_id = ?1 ORDER BY version DESC",
+        )?;
+        let rows = statement.query_map([id.to_string()], |row| {
+            Ok((row.get::<_, i64>(0)?, encoded_event(row, 1)?))
+        })?;
+        let mut last_position = None;
+        for row in rows {
+            let (position, encoded) = row?;
+            let last_position = *last_position.get_or_insert(position);
+            // upcasters may drop the latest stored events
+            if let Some(event) = self
+                .codec
+                .decode_all(&encoded)
+                .map_err(SqliteEventStoreError::Codec)?
+                .pop()
+            {
+                return Ok(Some((last_position, event)));
+            }
+        }
+        Ok(None)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn append(
+        &self,
+        id: &A::Id,
+        expected_version: Option<&A::Version>,
+        new_events: &[EventEnvelope<&A::Event>],
+    ) -> Result<(), RepositoryError<A::Id, A::Version, SqliteEventStoreError<C::Error, A::Error>>>
+    where
+        A::Id: Clone,
+        A::Version: Clone,
+    {
+        if new_events.is_empty() {
+            return Ok(());
+        }
+
+        let mut connection = self.connection.lock().unwrap();
+        let backend_error = |e: rusqlite::Error| RepositoryError::Backend(e.into());
+        // immediate, so that a concurrent writer waits for the write lock rather than failing
+        // with SQLITE_BUSY when upgrading from a read
+        let transaction = connection
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(backend_error)?;
+        if is_deleted(&transaction, id).map_err(backend_error)? {
+            return Err(RepositoryError::Deleted { id: id.clone() });
+        }
+        let last_event = self
+            .last_event(&transaction, id)
+            .map_err(RepositoryError::Backend)?;
+        let last_position = check_version(id, expected_version, last_event)?;
+        let recorded_at = recorded_at(last_recorded_at(&transaction, id).map_err(backend_error)?);
+
+        let inserted = {
+            let mut statement = transaction
+                .prepare_cached(
+                    "INSERT INTO events (aggregate_id, version, event_type, schema_version, payload, metadata) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .map_err(backend_error)?;
+            let mut inserted = Ok(());
+            for (position, envelope) in (last_position + 1..).zip(new_events) {
+                let encoded = self
+                    .codec
+                    .encode(envelope.event)
+                    .map_err(|e| RepositoryError::Backend(SqliteEventStoreError::Codec(e)))?;
+                if let Err(e) = statement.execute(rusqlite::params![
+                    id.to_string(),
+                    position,
+                    encoded.event_type,
+                    encoded.schema_version,
+                    encoded.payload,
+                    encode_metadata(&EventEnvelope {
+                        recorded_at,
+                        ..envelope.clone()
+                    })
+                ]) {
+                    inserted = Err(e);
+                    break;
+                }
+            }
+            if inserted.is_ok() && self.outbox {
+                inserted = transaction
+                    .execute(
+                        "INSERT INTO outbox (aggregate_id, version) SELECT aggregate_id, version FROM events WHERE aggregate_id = ?1 AND version > ?2 ORDER BY version ASC",
+                        rusqlite::params![id.to_string(), last_position],
+                    )
+                    .map(|_| ());
+            }
+            inserted
+        };
+        match inserted {
+            Ok(()) => {
+                transaction.commit().map_err(backend_error)?;
+                Ok(())
+            }
+            Err(rusqlite::Error::SqliteFailure(e, message))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                // another writer appended to the stream concurrently
+                drop(transaction);
+                let last_event = self
+                    .last_event(&connection, id)
+                    .map_err(RepositoryError::Backend)?;
+                check_version(id, expected_version, last_event)?;
+                Err(backend_error(rusqlite::Error::SqliteFailure(e, message)))
+            }
+            Err(e) => Err(backend_error(e)),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn remove(
+        &self,
+        id: &A::Id,
+        expected_version: &A::Version,
+        purge: bool,
+    ) -> Result<(), RepositoryError<A::Id, A::Version, SqliteEventStoreError<C::Error, A::Error>>>
+    where
+        A::Id: Clone,
+        A::Version: Clone,
+    {
+        let mut connection = self.connection.lock().unwrap();
+        let backend_error = |e: rusqlite::Error| RepositoryError::Backend(e.into());
+        let transaction = connection
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(backend_error)?;
+        let deleted = is_deleted(&transaction, id).map_err(backend_error)?;
+        let last_event = self
+            .last_event(&transaction, id)
+            .map_err(RepositoryError::Backend)?;
+        if deleted && (!purge || last_event.is_none()) {
+            return Err(RepositoryError::Deleted { id: id.clone() });
+        }
+        check_version(id, Some(expected_version), last_event)?;
+
+        transaction
+            .execute(
+                "INSERT OR IGNORE INTO tombstones (aggregate_id) VALUES (?1)",
+                [id.to_string()],
+            )
+            .map_err(backend_error)?;
+        if purge {
+            for statement in [
+                "DELETE FROM events WHERE aggregate_id = ?1",
+                "DELETE FROM outbox WHERE aggregate_id = ?1",
+            ] {
+                transaction
+                    .execute(statement, [id.to_string()])
+                    .map_err(backend_error)?;
+            }
+        }
+        transaction.commit().map_err(backend_error)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, C> Repository for SqliteEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Clone + Debug + Display + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    type Aggregate = A;
+    type Error = RepositoryError<A::Id, A::Version, SqliteEventStoreError<C::Error, A::Error>>;
+
+    async fn find(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<Option<Self::Aggregate>, Self::Error> {
+        let events = {
+            let connection = self.connection.lock().unwrap();
+            if is_deleted(&connection, id).map_err(|e| RepositoryError::Backend(e.into()))? {
+                return Ok(None);
+            }
+            self.read_log(&connection, id)
+                .map_err(RepositoryError::Backend)?
+        };
+        if events.is_empty() {
+            return Ok(None);
+        }
+        A::replay(events.into_iter().map(|envelope| envelope.event))
+            .map(Some)
+            .map_err(|e| RepositoryError::Backend(SqliteEventStoreError::Aggregate(e)))
+    }
+
+    async fn store(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[<Self::Aggregate as Aggregate>::Event],
+    ) -> Result<(), Self::Error> {
+        let new_events = new_events
+            .iter()
+            .map(EventEnvelope::new)
+            .collect::<Vec<_>>();
+        self.append(id, expected_version, &new_events)
+    }
+}
+
+fn encoded_event(row: &rusqlite::Row<'_>, offset: usize) -> rusqlite::Result<EncodedEvent> {
+    Ok(EncodedEvent {
+        event_type: row.get(offset)?,
+        schema_version: row.get(offset + 1)?,
+        payload: row.get(offset + 2)?,
+    })
+}
+
+fn is_deleted<I: Display>(connection: &rusqlite::Connection, id: &I) -> rusqlite::Result<bool> {
+    connection
+        .prepare_cached("SELECT EXISTS (SELECT 1 FROM tombstones WHERE aggregate_id = ?1)")?
+        .query_row([id.to_string()], |row| row.get(0))
+}
+
+// undecodable metadata is left for reads to report
+fn last_recorded_at<I: Display>(
+    connection: &rusqlite::Connection,
+    id: &I,
+) -> rusqlite::Result<Option<SystemTime>> {
+    let metadata = connection
+        .prepare_cached(
+            "SELECT metadata FROM events WHERE aggregate_id = ?1 ORDER BY version DESC LIMIT 1",
+        )?
+        .query_row([id.to_string()], |row| row.get::<_, Vec<u8>>(0))
+        .optional()?;
+    Ok(metadata
+        .and_then(|metadata| decode_metadata(&metadata, ()))
+        .map(|envelope| envelope.recorded_at))
+}
+
+fn check_version<I, V, E, B>(
+    id: &I,
+    expected_version: Option<&V>,
+    last_event: Option<(i64, E)>,
+) -> Result<i64, RepositoryError<I, V, B>>
+where
+    I: Clone,
+    V: Clone + Eq,
+    E: Event<Version = V>,
+{
+    match (expected_version, last_event) {
+        (None, None) => {
+            // create
+            Ok(0)
+        }
+        (None, Some(_)) => Err(RepositoryError::AlreadyExists { id: id.clone() }),
+        (Some(expected_version), Some((position, event))) => {
+            // update
+            let actual_version = event.version();
+            if actual_version != *expected_version {
+                return Err(RepositoryError::VersionConflict {
+                    expected: expected_version.clone(),
+                    actual: actual_version,
+                });
+            }
+            Ok(position)
+        }
+        (Some(_), None) => Err(RepositoryError::NotFound { id: id.clone() }),
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, C> EventReader for SqliteEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Clone + Debug + Display + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    async fn read_events(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        after_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+    ) -> Result<Vec<<Self::Aggregate as Aggregate>::Event>, Self::Error> {
+        let connection = self.connection.lock().unwrap();
+        let events = self
+            .read_log(&connection, id)
+            .map_err(RepositoryError::Backend)?;
+        Ok(events
+            .into_iter()
+            .map(|envelope| envelope.event)
+            .filter(|event| {
+                after_version.is_none_or(|after_version| event.version() > *after_version)
+            })
+            .collect())
+    }
+
+    async fn is_deleted(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+    ) -> Result<bool, Self::Error> {
+        let connection = self.connection.lock().unwrap();
+        is_deleted(&connection, id).map_err(|e| RepositoryError::Backend(e.into()))
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, C> EnvelopeRepository for SqliteEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Clone + Debug + Display + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    async fn store_envelopes(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+        new_events: &[EventEnvelope<<Self::Aggregate as Aggregate>::Event>],
+    ) -> Result<(), Self::Error> {
+        let new_events = new_events
+            .iter()
+            .map(EventEnvelope::as_ref)
+            .collect::<Vec<_>>();
+        self.append(id, expected_version, &new_events)
+    }
+
+    async fn read_envelopes(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        after_version: Option<&<Self::Aggregate as Aggregate>::Version>,
+    ) -> Result<Vec<EventEnvelope<<Self::Aggregate as Aggregate>::Event>>, Self::Error> {
+        let connection = self.connection.lock().unwrap();
+        let envelopes = self
+            .read_log(&connection, id)
+            .map_err(RepositoryError::Backend)?;
+        Ok(envelopes
+            .into_iter()
+            .filter(|envelope| {
+                after_version.is_none_or(|after_version| envelope.event.version() > *after_version)
+            })
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, C> DeletableRepository for SqliteEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Clone + Debug + Display + Send + Sync,
+    A::Version: Clone + Debug + Send + Sync,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    async fn delete(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        self.remove(id, expected_version, false)
+    }
+
+    async fn purge(
+        &self,
+        id: &<Self::Aggregate as Aggregate>::Id,
+        expected_version: &<Self::Aggregate as Aggregate>::Version,
+    ) -> Result<(), Self::Error> {
+        self.remove(id, expected_version, true)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, C> Outbox for SqliteEventStore<A, C>
+where
+    A: Aggregate + Send + Sync,
+    A::Error: Send + 'static,
+    A::Event: Send + Sync,
+    A::Id: Display,
+    C: EventCodec<Event = A::Event> + Send + Sync,
+    C::Error: Send + 'static,
+{
+    type Event = A::Event;
+    type Error = SqliteEventStoreError<C::Error, A::Error>;
+
+    async fn pending(&self, limit: usize) -> Result<Vec<OutboxEntry<Self::Event>>, Self::Error> {
+        if !self.outbox {
+            return Err(SqliteEventStoreError::OutboxDisabled);
+        }
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT outbox.sequence, events.version, events.event_type, events.schema_version, events.payload, events.metadata FROM outbox JOIN events ON events.aggregate_id = outbox.aggregate_id AND events.version = outbox.version ORDER BY outbox.sequence ASC LIMIT ?1",
+        )?;
+        let rows = statement.query_map([i64::try_from(limit).unwrap_or(i64::MAX)], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                encoded_event(row, 2)?,
+                row.get::<_, Vec<u8>>(5)?,
+            ))
+        })?;
+        let mut entries = vec![];
+        for row in rows {
+            let (sequence, position, encoded, metadata) = row?;
+            for event in self
+                .codec
+                .decode_all(&encoded)
+                .map_err(SqliteEventStoreError::Codec)?
+            {
+                entries.push(OutboxEntry {
+                    sequence: sequence as u64,
+                    envelope: decode_metadata(&metadata, event)
+                        .ok_or(SqliteEventStoreError::InvalidMetadata { position })?,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    async fn mark_dispatched(&self, sequences: &[u64]) -> Result<(), Self::Error> {
+        if !self.outbox {
+            return Err(SqliteEventStoreError::OutboxDisabled);
+        }
+        let mut connection = self.connection.lock().unwrap();
+        let transaction =
+            connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        {
+            let mut statement =
+                transaction.prepare_cached("DELETE FROM outbox WHERE sequence = ?1")?;
+            for sequence in sequences {
+                statement.execute([*sequence as i64])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum SqliteEventStoreError<C, A> {
+    Sqlite(rusqlite::Error),
+    Codec(C),
+    Aggregate(A),
+    InvalidMetadata { position: i64 },
+    OutboxDisabled,
+}
+
+impl<C, A> From<rusqlite::Error> for SqliteEventStoreError<C, A> {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
+
+impl<C, A> Display for SqliteEventStoreError<C, A>
+where
+    C: Display,
+    A: Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sqlite(e) => Display::fmt(e, f),
+            Self::Codec(e) => Display::fmt(e, f),
+            Self::Aggregate(e) => Display::fmt(e, f),
+            Self::InvalidMetadata { position } => {
+                write!(f, "Invalid event metadata (position = {})", position)
+            }
+            Self::OutboxDisabled => Display::fmt(&OutboxDisabled, f),
+        }
+    }
+}
+
+impl<C, A> std::error::Error for SqliteEventStoreError<C, A>
+where
+    C: std::error::Error + 'static,
+    A: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Sqlite(e) => Some(e),
+            Self::Codec(e) => Some(e),
+            Self::Aggregate(e) => Some(e),
+            Self::InvalidMetadata { .. } | Self::OutboxDisabled => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::v2::testing::repository_conformance;
+    use crate::v2::tests::{
+        AggregateEventCodec, AggregateId, AggregateImpl, AggregateVersion, Fixture, created,
+    };
+
+    #[tokio::test]
+    async fn test_find_and_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let repository =
+            SqliteEventStore::<AggregateImpl, _>::open(&path, AggregateEventCodec).unwrap();
+        let id = AggregateId("1".to_owned());
+
+        assert!(repository.find(&id).await.unwrap().is_none());
+
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), AggregateVersion(1));
+
+        let (updated_aggregate, events) = found_aggregate.update().unwrap();
+        repository
+            .store(&id, Some(&found_aggregate.version()), &events)
+            .await
+            .unwrap();
+
+        // reopen
+        let repository =
+            SqliteEventStore::<AggregateImpl, _>::open(&path, AggregateEventCodec).unwrap();
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(found_aggregate.version(), updated_aggregate.version());
+        assert_eq!(
+            repository
+                .read_events(&id, Some(&AggregateVersion(1)))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_rejects_conflicts() {
+        let repository =
+            SqliteEventStore::<AggregateImpl, _>::open_in_memory(AggregateEventCodec).unwrap();
+        let id = AggregateId("1".to_owned());
+
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(1)), &[created("1", 1)])
+                .await,
+            Err(RepositoryError::NotFound { .. })
+        ));
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository.store(&id, None, &[created("1", 1)]).await,
+            Err(RepositoryError::AlreadyExists { .. })
+        ));
+
+        let found_aggregate = repository.find(&id).await.unwrap().unwrap();
+        let (_, events) = found_aggregate.update().unwrap();
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(2)), &events)
+                .await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unique_constraint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let repository =
+            SqliteEventStore::<AggregateImpl, _>::open(&path, AggregateEventCodec).unwrap();
+        let id = AggregateId("1".to_owned());
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let result = connection.execute(
+            "INSERT INTO events (aggregate_id, version, event_type, schema_version, payload, metadata) VALUES (?1, 1, 'AggregateCreated', 1, x'00', x'')",
+            [id.to_string()],
+        );
+        assert!(matches!(
+            result,
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let barrier = std::sync::Barrier::new(2);
+
+        for n in 0..50 {
+            let id = AggregateId(n.to_string());
+            let results = std::thread::scope(|scope| {
+                let handles = (0..2)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let repository = SqliteEventStore::<AggregateImpl, _>::open(
+                                &path,
+                                AggregateEventCodec,
+                            )
+                            .unwrap();
+                            barrier.wait();
+                            futures::executor::block_on(repository.store(
+                                &id,
+                                None,
+                                &[created(&id.0, 1)],
+                            ))
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+            assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+            assert!(
+                results
+                    .iter()
+                    .all(|result| result.as_ref().err().is_none_or(|e| e.is_conflict()))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_envelopes() {
+        let repository =
+            SqliteEventStore::<AggregateImpl, _>::open_in_memory(AggregateEventCodec).unwrap();
+        let id = AggregateId("1".to_owned());
+        let mut envelope = EventEnvelope::new(created("1", 1))
+            .with_causation_id(uuid::Uuid::new_v4())
+            .with_actor("alice");
+        // the store records its own time
+        envelope.recorded_at = std::time::SystemTime::UNIX_EPOCH;
+        let before = std::time::SystemTime::now();
+
+        repository
+            .store_envelopes(&id, None, std::slice::from_ref(&envelope))
+            .await
+            .unwrap();
+        let envelopes = repository.read_envelopes(&id, None).await.unwrap();
+        assert!(envelopes[0].recorded_at >= before);
+        assert_eq!(
+            envelopes,
+            vec![EventEnvelope {
+                recorded_at: envelopes[0].recorded_at,
+                ..envelope
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_outbox() {
+        let repository = SqliteEventStore::<AggregateImpl, _>::open_in_memory(AggregateEventCodec)
+            .unwrap()
+            .with_outbox();
+        let id = AggregateId("1".to_owned());
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+        let (_, events) = repository
+            .find(&id)
+            .await
+            .unwrap()
+            .unwrap()
+            .update()
+            .unwrap();
+        repository
+            .store(&id, Some(&AggregateVersion(1)), &events)
+            .await
+            .unwrap();
+
+        let entries = repository.pending(10).await.unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.envelope.clone())
+                .collect::<Vec<_>>(),
+            repository.read_envelopes(&id, None).await.unwrap()
+        );
+        repository
+            .mark_dispatched(&[entries[0].sequence])
+            .await
+            .unwrap();
+        assert_eq!(repository.pending(10).await.unwrap(), entries[1..]);
+        assert_eq!(repository.pending(0).await.unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_outbox_disabled() {
+        let repository =
+            SqliteEventStore::<AggregateImpl, _>::open_in_memory(AggregateEventCodec).unwrap();
+        repository
+            .store(&AggregateId("1".to_owned()), None, &[created("1", 1)])
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository.pending(10).await,
+            Err(SqliteEventStoreError::OutboxDisabled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_purge() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let repository = SqliteEventStore::<AggregateImpl, _>::open(&path, AggregateEventCodec)
+            .unwrap()
+            .with_outbox();
+        let id = AggregateId("1".to_owned());
+        repository
+            .store(&id, None, &[created("1", 1)])
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repository.delete(&id, &AggregateVersion(2)).await,
+            Err(RepositoryError::VersionConflict { .. })
+        ));
+        repository.delete(&id, &AggregateVersion(1)).await.unwrap();
+        assert!(repository.is_deleted(&id).await.unwrap());
+        assert!(repository.find(&id).await.unwrap().is_none());
+        assert_eq!(repository.read_events(&id, None).await.unwrap().len(), 1);
+        assert!(matches!(
+            repository
+                .store(&id, Some(&AggregateVersion(1)), &[created("1", 2)])
+                .await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+
+        repository.purge(&id, &AggregateVersion(1)).await.unwrap();
+        assert!(repository.read_events(&id, None).await.unwrap().is_empty());
+        assert!(repository.pending(10).await.unwrap().is_empty());
+        assert!(matches!(
+            repository.purge(&id, &AggregateVersion(1)).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+
+        // the tombstone is persisted
+        drop(repository);
+        let repository =
+            SqliteEventStore::<AggregateImpl, _>::open(&path, AggregateEventCodec).unwrap();
+        assert!(matches!(
+            repository.store(&id, None, &[created("1", 1)]).await,
+            Err(RepositoryError::Deleted { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        let dir = tempfile::tempdir().unwrap();
+        let n = AtomicUsize::new(0);
+        repository_conformance(&Fixture(|| -> SqliteEventStore<AggregateImpl, _> {
+            SqliteEventStore::open(
+                dir.path()
+                    .join(format!("{}.db", n.fetch_add(1, Ordering::SeqCst))),
+                AggregateEventCodec,
+            )
+            .unwrap()
+        }))
+        .await;
+    }
+}
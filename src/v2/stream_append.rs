@@ -0,0 +1,16 @@
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamAppend<I, V, E> {
+    pub id: I,
+    pub expected_version: Option<V>,
+    pub events: Vec<E>,
+}
+
+impl<I, V, E> StreamAppend<I, V, E> {
+    pub fn new(id: I, expected_version: Option<V>, events: Vec<E>) -> Self {
+        Self {
+            id,
+            expected_version,
+            events,
+        }
+    }
+}
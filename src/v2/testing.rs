@@ -0,0 +1,448 @@
+use std::fmt::{Debug, Write as _};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Barrier};
+use std::task::{Context, Poll, Wake, Waker};
+
+use super::{Aggregate, Event, Repository};
+
+pub trait RepositoryFixture {
+    type Repository: Repository;
+
+    fn repository(&self) -> Self::Repository;
+
+    fn id(&self, n: usize) -> <<Self::Repository as Repository>::Aggregate as Aggregate>::Id;
+
+    fn create(
+        &self,
+        id: &<<Self::Repository as Repository>::Aggregate as Aggregate>::Id,
+    ) -> Vec<<<Self::Repository as Repository>::Aggregate as Aggregate>::Event>;
+
+    fn update(
+        &self,
+        aggregate: &<Self::Repository as Repository>::Aggregate,
+    ) -> Vec<<<Self::Repository as Repository>::Aggregate as Aggregate>::Event>;
+}
+
+pub async fn repository_conformance<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Id: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    find_missing(fixture).await;
+    create_and_update(fixture).await;
+    create_twice(fixture).await;
+    stale_expected_version(fixture).await;
+    expected_version_for_missing(fixture).await;
+    empty_new_events(fixture).await;
+    multiple_aggregates(fixture).await;
+    concurrent_writers(fixture).await;
+}
+
+async fn find_missing<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+{
+    let repository = fixture.repository();
+    let id = fixture.id(1);
+    assert!(repository.find(&id).await.unwrap().is_none());
+}
+
+async fn create_and_update<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Id: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+    let id = fixture.id(1);
+
+    let events = fixture.create(&id);
+    repository.store(&id, None, &events).await.unwrap();
+    let found = repository.find(&id).await.unwrap().unwrap();
+    assert_eq!(found.id(), id);
+    assert_eq!(Some(found.version()), events.last().map(Event::version));
+
+    let events = fixture.update(&found);
+    repository
+        .store(&id, Some(&found.version()), &events)
+        .await
+        .unwrap();
+    let found = repository.find(&id).await.unwrap().unwrap();
+    assert_eq!(found.id(), id);
+    assert_eq!(Some(found.version()), events.last().map(Event::version));
+}
+
+async fn create_twice<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+    let id = fixture.id(1);
+
+    let events = fixture.create(&id);
+    repository.store(&id, None, &events).await.unwrap();
+    assert!(repository.store(&id, None, &events).await.is_err());
+
+    let found = repository.find(&id).await.unwrap().unwrap();
+    assert_eq!(Some(found.version()), events.last().map(Event::version));
+}
+
+async fn stale_expected_version<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+    let id = fixture.id(1);
+
+    repository
+        .store(&id, None, &fixture.create(&id))
+        .await
+        .unwrap();
+    let stale = repository.find(&id).await.unwrap().unwrap();
+    let events = fixture.update(&stale);
+    repository
+        .store(&id, Some(&stale.version()), &events)
+        .await
+        .unwrap();
+
+    let stale_events = fixture.update(&stale);
+    assert!(
+        repository
+            .store(&id, Some(&stale.version()), &stale_events)
+            .await
+            .is_err()
+    );
+
+    let found = repository.find(&id).await.unwrap().unwrap();
+    assert_eq!(Some(found.version()), events.last().map(Event::version));
+}
+
+async fn expected_version_for_missing<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+{
+    let repository = fixture.repository();
+    let id = fixture.id(1);
+    let other_id = fixture.id(2);
+
+    repository
+        .store(&other_id, None, &fixture.create(&other_id))
+        .await
+        .unwrap();
+    let other = repository.find(&other_id).await.unwrap().unwrap();
+
+    let events = fixture.create(&id);
+    assert!(
+        repository
+            .store(&id, Some(&other.version()), &events)
+            .await
+            .is_err()
+    );
+    assert!(repository.find(&id).await.unwrap().is_none());
+}
+
+async fn empty_new_events<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+    let id = fixture.id(1);
+
+    repository.store(&id, None, &[]).await.unwrap();
+    assert!(repository.find(&id).await.unwrap().is_none());
+
+    let events = fixture.create(&id);
+    repository.store(&id, None, &events).await.unwrap();
+    let found = repository.find(&id).await.unwrap().unwrap();
+    repository
+        .store(&id, Some(&found.version()), &[])
+        .await
+        .unwrap();
+    let found = repository.find(&id).await.unwrap().unwrap();
+    assert_eq!(Some(found.version()), events.last().map(Event::version));
+}
+
+async fn multiple_aggregates<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Id: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+    let id1 = fixture.id(1);
+    let id2 = fixture.id(2);
+
+    repository
+        .store(&id1, None, &fixture.create(&id1))
+        .await
+        .unwrap();
+    let events2 = fixture.create(&id2);
+    repository.store(&id2, None, &events2).await.unwrap();
+
+    let found1 = repository.find(&id1).await.unwrap().unwrap();
+    let events1 = fixture.update(&found1);
+    repository
+        .store(&id1, Some(&found1.version()), &events1)
+        .await
+        .unwrap();
+
+    let found1 = repository.find(&id1).await.unwrap().unwrap();
+    assert_eq!(found1.id(), id1);
+    assert_eq!(Some(found1.version()), events1.last().map(Event::version));
+    let found2 = repository.find(&id2).await.unwrap().unwrap();
+    assert_eq!(found2.id(), id2);
+    assert_eq!(Some(found2.version()), events2.last().map(Event::version));
+}
+
+async fn concurrent_writers<F>(fixture: &F)
+where
+    F: RepositoryFixture,
+    <F::Repository as Repository>::Error: Debug,
+    <<F::Repository as Repository>::Aggregate as Aggregate>::Version: Debug,
+{
+    let repository = fixture.repository();
+
+    // a store that is not atomic fails only some of the races
+    for n in 1..=8 {
+        let id = fixture.id(n);
+        repository
+            .store(&id, None, &fixture.create(&id))
+            .await
+            .unwrap();
+        let found = repository.find(&id).await.unwrap().unwrap();
+        let version = found.version();
+        let events = (0..4).map(|_| fixture.update(&found)).collect::<Vec<_>>();
+
+        let results = race(
+            events
+                .iter()
+                .map(|events| {
+                    let store = repository.store(&id, Some(&version), events);
+                    Box::pin(async move { store.await.is_ok() }) as Pin<Box<_>>
+                })
+                .collect(),
+        );
+        assert_eq!(results.iter().filter(|ok| **ok).count(), 1);
+
+        let found = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(Some(found.version()), events[0].last().map(Event::version));
+    }
+}
+
+// Polls each future to completion on its own thread, all of them released at once, so that
+// they actually run concurrently. The futures run outside of any async runtime.
+pub(crate) fn race<'a, T: Send>(
+    futures: Vec<Pin<Box<dyn Future<Output = T> + Send + 'a>>>,
+) -> Vec<T> {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let barrier = Barrier::new(futures.len());
+    std::thread::scope(|scope| {
+        let handles = futures
+            .into_iter()
+            .map(|mut future| {
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+                    let mut cx = Context::from_waker(&waker);
+                    barrier.wait();
+                    loop {
+                        if let Poll::Ready(t) = future.as_mut().poll(&mut cx) {
+                            return t;
+                        }
+                        std::thread::park();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("racing future not to panic"))
+            .collect()
+    })
+}
+
+pub fn given<A, I>(events: I) -> Given<A>
+where
+    A: Aggregate,
+    I: IntoIterator<Item = A::Event>,
+{
+    Given {
+        events: events.into_iter().collect(),
+    }
+}
+
+pub struct Given<A: Aggregate> {
+    events: Vec<A::Event>,
+}
+
+impl<A> Given<A>
+where
+    A: Aggregate,
+    A::Error: Debug,
+{
+    // the command sees no aggregate when no events are given, as with `execute`
+    pub fn when<F, E>(self, command: F) -> When<A::Event, E>
+    where
+        F: FnOnce(Option<&A>) -> Result<Vec<A::Event>, E>,
+    {
+        let aggregate = if self.events.is_empty() {
+            None
+        } else {
+            Some(A::replay(self.events).expect("given events to replay"))
+        };
+        When {
+            result: command(aggregate.as_ref()),
+        }
+    }
+}
+
+pub struct When<T, E> {
+    result: Result<Vec<T>, E>,
+}
+
+impl<T, E> When<T, E>
+where
+    T: Debug + PartialEq,
+    E: Debug,
+{
+    pub fn then_expect_events<I>(self, expected: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let actual = match self.result {
+            Err(e) => panic!("expected events but got error: {:?}", e),
+            Ok(actual) => actual,
+        };
+        let expected = expected.into_iter().collect::<Vec<_>>();
+        if let Some(diff) = diff_events(&expected, &actual) {
+            panic!(
+                "emitted events did not match (- expected, + actual)\n{}",
+                diff
+            );
+        }
+    }
+
+    pub fn then_expect_error<P>(self, predicate: P)
+    where
+        P: FnOnce(&E) -> bool,
+    {
+        match self.result {
+            Ok(actual) => panic!("expected error but got events: {:?}", actual),
+            Err(e) => assert!(predicate(&e), "unexpected error: {:?}", e),
+        }
+    }
+}
+
+fn diff_events<T>(expected: &[T], actual: &[T]) -> Option<String>
+where
+    T: Debug + PartialEq,
+{
+    if expected == actual {
+        return None;
+    }
+    let mut diff = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => writeln!(diff, "  [{}] {:?}", i, e),
+            (Some(e), Some(a)) => writeln!(diff, "- [{}] {:?}", i, e)
+                .and_then(|_| writeln!(diff, "+ [{}] {:?}", i, a)),
+            (Some(e), None) => writeln!(diff, "- [{}] {:?}", i, e),
+            (None, Some(a)) => writeln!(diff, "+ [{}] {:?}", i, a),
+            (None, None) => unreachable!(),
+        }
+        .expect("writing to a String to succeed");
+    }
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::tests::{AggregateCreated, AggregateEvent, AggregateImpl, AggregateUpdated};
+
+    fn created(version: u16) -> AggregateEvent {
+        AggregateEvent::Created(AggregateCreated {
+            id: "1".to_owned(),
+            version,
+        })
+    }
+
+    fn updated(version: u16) -> AggregateEvent {
+        AggregateEvent::Updated(AggregateUpdated {
+            id: "1".to_owned(),
+            version,
+        })
+    }
+
+    #[test]
+    fn test_then_expect_events() {
+        given::<AggregateImpl, _>([created(1)])
+            .when(|aggregate| aggregate.unwrap().update().map(|(_, events)| events))
+            .then_expect_events([updated(2)]);
+
+        given::<AggregateImpl, _>([created(1), updated(2)])
+            .when(|aggregate| aggregate.unwrap().update().map(|(_, events)| events))
+            .then_expect_events([updated(3)]);
+    }
+
+    #[test]
+    fn test_given_no_events() {
+        given::<AggregateImpl, _>([])
+            .when(|aggregate| match aggregate {
+                None => Ok(vec![created(1)]),
+                Some(_) => Err(std::io::Error::other("Already created")),
+            })
+            .then_expect_events([created(1)]);
+
+        given::<AggregateImpl, _>([created(1)])
+            .when(|aggregate| match aggregate {
+                None => Ok(vec![created(1)]),
+                Some(_) => Err(std::io::Error::other("Already created")),
+            })
+            .then_expect_error(|e| e.to_string() == "Already created");
+    }
+
+    #[test]
+    #[should_panic(expected = "emitted events did not match")]
+    fn test_then_expect_events_mismatch() {
+        given::<AggregateImpl, _>([created(1)])
+            .when(|aggregate| aggregate.unwrap().update().map(|(_, events)| events))
+            .then_expect_events([updated(3)]);
+    }
+
+    #[test]
+    fn test_then_expect_error() {
+        given::<AggregateImpl, _>([created(1)])
+            .when(|_| Err::<Vec<AggregateEvent>, _>(std::io::Error::other("Invalid command")))
+            .then_expect_error(|e| e.to_string() == "Invalid command");
+    }
+
+    #[test]
+    fn test_diff_events() {
+        assert_eq!(diff_events(&[1, 2], &[1, 2]), None);
+        assert_eq!(
+            diff_events(&[1, 2], &[1, 3, 4]).unwrap(),
+            "  [0] 1\n- [1] 2\n+ [1] 3\n+ [2] 4\n"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use super::{EncodedEvent, EventCodec};
+
+pub trait Upcaster {
+    type Error: std::error::Error;
+
+    // returns the events replacing `event`: none to drop it, several to split it
+    fn upcast(&self, event: EncodedEvent) -> Result<Vec<EncodedEvent>, Self::Error>;
+}
+
+impl<F, E> Upcaster for F
+where
+    F: Fn(EncodedEvent) -> Result<Vec<EncodedEvent>, E>,
+    E: std::error::Error,
+{
+    type Error = E;
+
+    fn upcast(&self, event: EncodedEvent) -> Result<Vec<EncodedEvent>, Self::Error> {
+        self(event)
+    }
+}
+
+pub struct UpcasterChain<E> {
+    upcasters: Vec<Box<dyn Upcaster<Error = E> + Send + Sync>>,
+}
+
+impl<E> UpcasterChain<E> {
+    pub fn new() -> Self {
+        Self { upcasters: vec![] }
+    }
+
+    pub fn with<U>(mut self, upcaster: U) -> Self
+    where
+        U: Upcaster<Error = E> + Send + Sync + 'static,
+    {
+        self.upcasters.push(Box::new(upcaster));
+        self
+    }
+}
+
+impl<E> Default for UpcasterChain<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Upcaster for UpcasterChain<E>
+where
+    E: std::error::Error,
+{
+    type Error = E;
+
+    fn upcast(&self, event: EncodedEvent) -> Result<Vec<EncodedEvent>, Self::Error> {
+        // each upcaster sees the output of the previous one
+        self.upcasters
+            .iter()
+            .try_fold(vec![event], |events, upcaster| {
+                events.into_iter().try_fold(vec![], |mut upcasted, event| {
+                    upcasted.extend(upcaster.upcast(event)?);
+                    Ok(upcasted)
+                })
+            })
+    }
+}
+
+pub struct UpcastingCodec<C, U> {
+    codec: C,
+    upcaster: U,
+}
+
+impl<C, U> UpcastingCodec<C, U> {
+    pub fn new(codec: C, upcaster: U) -> Self {
+        Self { codec, upcaster }
+    }
+}
+
+impl<C, U> EventCodec for UpcastingCodec<C, U>
+where
+    C: EventCodec,
+    C::Error: 'static,
+    U: Upcaster,
+    U::Error: 'static,
+{
+    type Event = C::Event;
+    type Error = UpcastError<C::Error, U::Error>;
+
+    fn encode(&self, event: &Self::Event) -> Result<EncodedEvent, Self::Error> {
+        self.codec.encode(event).map_err(UpcastError::Codec)
+    }
+
+    fn decode(&self, encoded: &EncodedEvent) -> Result<Self::Event, Self::Error> {
+        let mut events = self.decode_all(encoded)?;
+        match events.len() {
+            1 => Ok(events.pop().unwrap()),
+            count => Err(UpcastError::UnexpectedCount(count)),
+        }
+    }
+
+    fn decode_all(&self, encoded: &EncodedEvent) -> Result<Vec<Self::Event>, Self::Error> {
+        self.upcaster
+            .upcast(encoded.clone())
+            .map_err(UpcastError::Upcaster)?
+            .iter()
+            .map(|encoded| self.codec.decode(encoded).map_err(UpcastError::Codec))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum UpcastError<C, U> {
+    Codec(C),
+    Upcaster(U),
+    UnexpectedCount(usize),
+}
+
+impl<C, U> std::fmt::Display for UpcastError<C, U>
+where
+    C: std::fmt::Display,
+    U: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Codec(e) => std::fmt::Display::fmt(e, f),
+            Self::Upcaster(e) => std::fmt::Display::fmt(e, f),
+            Self::UnexpectedCount(count) => {
+                write!(f, "Expected a single upcasted event (count = {})", count)
+            }
+        }
+    }
+}
+
+impl<C, U> std::error::Error for UpcastError<C, U>
+where
+    C: std::error::Error + 'static,
+    U: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Codec(e) => Some(e),
+            Self::Upcaster(e) => Some(e),
+            Self::UnexpectedCount(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::tests::{
+        AggregateCreated, AggregateEvent, AggregateEventCodec, AggregateUpdated,
+    };
+
+    fn encoded(event_type: &str, schema_version: u32, payload: &[u8]) -> EncodedEvent {
+        EncodedEvent {
+            event_type: event_type.to_owned(),
+            schema_version,
+            payload: payload.to_vec(),
+        }
+    }
+
+    // AggregateRegistered (v1) was renamed to AggregateCreated (v2)
+    fn rename(event: EncodedEvent) -> Result<Vec<EncodedEvent>, std::io::Error> {
+        match (event.event_type.as_str(), event.schema_version) {
+            ("AggregateRegistered", 1) => Ok(vec![encoded("AggregateCreated", 2, &event.payload)]),
+            _ => Ok(vec![event]),
+        }
+    }
+
+    // AggregateImported (v1) is now recorded as AggregateCreated followed by AggregateUpdated
+    fn split(event: EncodedEvent) -> Result<Vec<EncodedEvent>, std::io::Error> {
+        match (event.event_type.as_str(), event.schema_version) {
+            ("AggregateImported", 1) => {
+                let id = &event.payload[2..];
+                let mut created = 1u16.to_le_bytes().to_vec();
+                created.extend_from_slice(id);
+                Ok(vec![
+                    encoded("AggregateCreated", 2, &created),
+                    encoded("AggregateUpdated", 2, &event.payload),
+                ])
+            }
+            _ => Ok(vec![event]),
+        }
+    }
+
+    // AggregateTouched (v1) no longer exists
+    fn drop_obsolete(event: EncodedEvent) -> Result<Vec<EncodedEvent>, std::io::Error> {
+        match event.event_type.as_str() {
+            "AggregateTouched" => Ok(vec![]),
+            _ => Ok(vec![event]),
+        }
+    }
+
+    fn chain() -> UpcasterChain<std::io::Error> {
+        UpcasterChain::new()
+            .with(rename)
+            .with(split)
+            .with(drop_obsolete)
+    }
+
+    #[test]
+    fn test_chain() {
+        let chain = chain();
+
+        assert_eq!(
+            chain
+                .upcast(encoded("AggregateRegistered", 1, b"\x01\x001"))
+                .unwrap(),
+            vec![encoded("AggregateCreated", 2, b"\x01\x001")]
+        );
+        assert_eq!(
+            chain
+                .upcast(encoded("AggregateImported", 1, b"\x02\x001"))
+                .unwrap(),
+            vec![
+                encoded("AggregateCreated", 2, b"\x01\x001"),
+                encoded("AggregateUpdated", 2, b"\x02\x001"),
+            ]
+        );
+        assert!(
+            chain
+                .upcast(encoded("AggregateTouched", 1, b""))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            chain
+                .upcast(encoded("AggregateUpdated", 2, b"\x02\x001"))
+                .unwrap(),
+            vec![encoded("AggregateUpdated", 2, b"\x02\x001")]
+        );
+    }
+
+    #[test]
+    fn test_upcasting_codec() {
+        let codec = UpcastingCodec::new(AggregateEventCodec, chain());
+
+        assert_eq!(
+            codec
+                .decode_all(&encoded("AggregateImported", 1, b"\x02\x001"))
+                .unwrap(),
+            vec![
+                AggregateEvent::Created(AggregateCreated {
+                    id: "1".to_owned(),
+                    version: 1,
+                }),
+                AggregateEvent::Updated(AggregateUpdated {
+                    id: "1".to_owned(),
+                    version: 2,
+                }),
+            ]
+        );
+        assert!(matches!(
+            codec.decode(&encoded("AggregateImported", 1, b"\x02\x001")),
+            Err(UpcastError::UnexpectedCount(2))
+        ));
+        assert!(
+            codec
+                .decode_all(&encoded("AggregateTouched", 1, b""))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_rename_json_field() {
+        #[derive(Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+        struct Renamed {
+            name: String,
+        }
+
+        impl crate::v2::EventSchema for Renamed {
+            fn event_type(&self) -> &'static str {
+                "Renamed"
+            }
+
+            fn schema_version(&self) -> u32 {
+                2
+            }
+        }
+
+        let upcaster = |mut event: EncodedEvent| -> Result<Vec<EncodedEvent>, serde_json::Error> {
+            if event.schema_version == 1 {
+                let mut value: serde_json::Value = serde_json::from_slice(&event.payload)?;
+                if let Some(object) = value.as_object_mut()
+                    && let Some(title) = object.remove("title")
+                {
+                    object.insert("name".to_owned(), title);
+                }
+                event.schema_version = 2;
+                event.payload = serde_json::to_vec(&value)?;
+            }
+            Ok(vec![event])
+        };
+        let codec = UpcastingCodec::new(crate::v2::JsonCodec::<Renamed>::new(), upcaster);
+
+        assert_eq!(
+            codec
+                .decode(&encoded("Renamed", 1, br#"{"title":"a"}"#))
+                .unwrap(),
+            Renamed {
+                name: "a".to_owned()
+            }
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use super::{Aggregate, Repository};
+use crate::v1;
+
+// A v2 aggregate seen as a v1 aggregate. The events produced since it was loaded are kept
+// until the aggregate is stored through a `V1Adapter`.
+pub struct PendingAggregate<A: Aggregate> {
+    aggregate: A,
+    id: A::Id,
+    version: A::Version,
+    pending_events: Vec<A::Event>,
+}
+
+impl<A: Aggregate> PendingAggregate<A>
+where
+    A::Event: Clone,
+{
+    pub fn new(aggregate: A) -> Self {
+        Self {
+            id: aggregate.id(),
+            version: aggregate.version(),
+            aggregate,
+            pending_events: vec![],
+        }
+    }
+
+    pub fn create(events: Vec<A::Event>) -> Result<Self, A::Error> {
+        let aggregate = A::replay(events.clone())?;
+        Ok(Self {
+            pending_events: events,
+            ..Self::new(aggregate)
+        })
+    }
+
+    pub fn apply(self, events: Vec<A::Event>) -> Result<Self, A::Error> {
+        let mut pending_events = self.pending_events;
+        pending_events.extend(events.iter().cloned());
+        let aggregate = events.into_iter().try_fold(self.aggregate, A::apply)?;
+        Ok(Self {
+            pending_events,
+            ..Self::new(aggregate)
+        })
+    }
+}
+
+impl<A: Aggregate> PendingAggregate<A> {
+    pub fn aggregate(&self) -> &A {
+        &self.aggregate
+    }
+
+    pub fn pending_events(&self) -> &[A::Event] {
+        &self.pending_events
+    }
+
+    pub fn into_inner(self) -> A {
+        self.aggregate
+    }
+}
+
+impl<A: Aggregate> v1::Aggregate for PendingAggregate<A> {
+    type Id = A::Id;
+    type Version = A::Version;
+
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+
+    fn version(&self) -> &Self::Version {
+        &self.version
+    }
+}
+
+// Exposes a v2 repository as a v1 repository. `store` appends the pending events of the
+// aggregate, so the expected version is the version of the stream before them. An aggregate
+// without pending events is rejected rather than silently left unstored.
+pub struct V1Adapter<R> {
+    repository: R,
+}
+
+impl<R> V1Adapter<R> {
+    pub fn new(repository: R) -> Self {
+        Self { repository }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.repository
+    }
+}
+
+#[async_trait::async_trait]
+impl<R> v1::Repository for V1Adapter<R>
+where
+    R: Repository + Send + Sync,
+    R::Error: 'static,
+    R::Aggregate: Send + Sync,
+    <R::Aggregate as Aggregate>::Event: Clone + Send + Sync,
+    <R::Aggregate as Aggregate>::Id: Send + Sync,
+    <R::Aggregate as Aggregate>::Version: Send + Sync,
+{
+    type Aggregate = PendingAggregate<R::Aggregate>;
+    type Error = V1AdapterError<R::Error>;
+
+    async fn find(
+        &self,
+        id: &<Self::Aggregate as v1::Aggregate>::Id,
+    ) -> Result<Option<Self::Aggregate>, Self::Error> {
+        Ok(self
+            .repository
+            .find(id)
+            .await
+            .map_err(V1AdapterError::Repository)?
+            .map(PendingAggregate::new))
+    }
+
+    async fn store(
+        &self,
+        expected_version: Option<&<Self::Aggregate as v1::Aggregate>::Version>,
+        aggregate: &Self::Aggregate,
+    ) -> Result<(), Self::Error> {
+        if aggregate.pending_events.is_empty() {
+            return Err(V1AdapterError::NoPendingEvents);
+        }
+        self.repository
+            .store(&aggregate.id, expected_version, &aggregate.pending_events)
+            .await
+            .map_err(V1AdapterError::Repository)
+    }
+}
+
+#[derive(Debug)]
+pub enum V1AdapterError<E> {
+    Repository(E),
+    NoPendingEvents,
+}
+
+impl<E> std::fmt::Display for V1AdapterError<E>
+where
+    E: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Repository(e) => std::fmt::Display::fmt(e, f),
+            Self::NoPendingEvents => write!(f, "No pending events to store"),
+        }
+    }
+}
+
+impl<E> std::error::Error for V1AdapterError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Repository(e) => Some(e),
+            Self::NoPendingEvents => None,
+        }
+    }
+}
+
+// Seeds an event-sourced repository with existing v1 aggregates, each recorded as a
+// single synthetic event. Aggregates already in the repository are skipped, so an interrupted
+// import can be run again. Returns the number of imported aggregates.
+pub async fn import_v1<R, I, F>(
+    repository: &R,
+    aggregates: I,
+    imported: F,
+) -> Result<usize, R::Error>
+where
+    R: Repository,
+    I: IntoIterator,
+    I::Item: v1::Aggregate<Id = <R::Aggregate as Aggregate>::Id>,
+    F: Fn(&I::Item) -> <R::Aggregate as Aggregate>::Event,
+{
+    let mut count = 0;
+    for aggregate in aggregates {
+        let id = v1::Aggregate::id(&aggregate);
+        if repository.find(id).await?.is_some() {
+            continue;
+        }
+        repository.store(id, None, &[imported(&aggregate)]).await?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v1::Repository as _;
+    use crate::v1::testing::{RepositoryFixture, repository_conformance};
+    use crate::v2::tests::{
+        AggregateCreated, AggregateEvent, AggregateId, AggregateImpl, AggregateVersion, created,
+    };
+    use crate::v2::{EventReader, InMemoryEventStore, RepositoryError};
+
+    struct AdapterFixture;
+
+    impl RepositoryFixture for AdapterFixture {
+        type Repository = V1Adapter<InMemoryEventStore<AggregateImpl>>;
+
+        fn repository(&self) -> Self::Repository {
+            V1Adapter::new(InMemoryEventStore::new())
+        }
+
+        fn create(&self, n: usize) -> PendingAggregate<AggregateImpl> {
+            PendingAggregate::create(vec![created(&n.to_string(), 1)]).unwrap()
+        }
+
+        fn update(
+            &self,
+            aggregate: &PendingAggregate<AggregateImpl>,
+        ) -> PendingAggregate<AggregateImpl> {
+            // only the new events are pending, as if `aggregate` had been stored and found again
+            let (_, events) = aggregate.aggregate().update().unwrap();
+            let stored = AggregateImpl {
+                id: aggregate.aggregate().id.clone(),
+                version: aggregate.aggregate().version.clone(),
+            };
+            PendingAggregate::new(stored).apply(events).unwrap()
+        }
+    }
+
+    struct StateAggregate {
+        id: AggregateId,
+        version: AggregateVersion,
+    }
+
+    impl v1::Aggregate for StateAggregate {
+        type Id = AggregateId;
+        type Version = AggregateVersion;
+
+        fn id(&self) -> &Self::Id {
+            &self.id
+        }
+
+        fn version(&self) -> &Self::Version {
+            &self.version
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v1_adapter() {
+        let repository = V1Adapter::new(InMemoryEventStore::<AggregateImpl>::new());
+        let id = AggregateId("1".to_owned());
+
+        assert!(repository.find(&id).await.unwrap().is_none());
+
+        let created = PendingAggregate::<AggregateImpl>::create(vec![AggregateEvent::Created(
+            AggregateCreated {
+                id: "1".to_owned(),
+                version: 1,
+            },
+        )])
+        .unwrap();
+        repository.store(None, &created).await.unwrap();
+
+        let found = repository.find(&id).await.unwrap().unwrap();
+        assert_eq!(v1::Aggregate::version(&found), &AggregateVersion(1));
+        assert!(found.pending_events().is_empty());
+
+        let (_, events) = found.aggregate().update().unwrap();
+        let updated = found.apply(events).unwrap();
+        assert_eq!(v1::Aggregate::version(&updated), &AggregateVersion(2));
+        repository
+            .store(Some(&AggregateVersion(1)), &updated)
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository.store(Some(&AggregateVersion(1)), &updated).await,
+            Err(V1AdapterError::Repository(
+                RepositoryError::VersionConflict { .. }
+            ))
+        ));
+        assert!(matches!(
+            repository
+                .store(
+                    Some(&AggregateVersion(2)),
+                    &repository.find(&id).await.unwrap().unwrap()
+                )
+                .await,
+            Err(V1AdapterError::NoPendingEvents)
+        ));
+        assert_eq!(
+            repository
+                .find(&id)
+                .await
+                .unwrap()
+                .unwrap()
+                .into_inner()
+                .version(),
+            AggregateVersion(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        repository_conformance(&AdapterFixture).await;
+    }
+
+    #[tokio::test]
+    async fn test_import_v1() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let aggregates = vec![
+            StateAggregate {
+                id: AggregateId("1".to_owned()),
+                version: AggregateVersion(3),
+            },
+            StateAggregate {
+                id: AggregateId("2".to_owned()),
+                version: AggregateVersion(1),
+            },
+        ];
+
+        let count = import_v1(&repository, aggregates, |aggregate| {
+            AggregateEvent::Created(AggregateCreated {
+                id: aggregate.id.0.clone(),
+                version: aggregate.version.0,
+            })
+        })
+        .await
+        .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            repository
+                .find(&AggregateId("1".to_owned()))
+                .await
+                .unwrap()
+                .unwrap()
+                .version(),
+            AggregateVersion(3)
+        );
+        assert_eq!(
+            repository
+                .read_events(&AggregateId("2".to_owned()), None)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_v1_resume() {
+        let repository = InMemoryEventStore::<AggregateImpl>::new();
+        let aggregate = |id: &str| StateAggregate {
+            id: AggregateId(id.to_owned()),
+            version: AggregateVersion(1),
+        };
+        let imported = |aggregate: &StateAggregate| created(&aggregate.id.0, 1);
+
+        // an import interrupted after the first aggregate
+        import_v1(&repository, [aggregate("1")], imported)
+            .await
+            .unwrap();
+        let count = import_v1(&repository, [aggregate("1"), aggregate("2")], imported)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            repository
+                .read_events(&AggregateId("1".to_owned()), None)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            repository
+                .find(&AggregateId("2".to_owned()))
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+}